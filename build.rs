@@ -1,7 +1,8 @@
 extern crate bindgen;
 
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() {
     let cosmosis_inc = env::var("COSMOSIS_INC").expect("COSMOSIS_INC should be defined");
@@ -67,14 +68,77 @@ fn main() {
          .whitelist_function("c_datablock_put_int_array_1d")
          .whitelist_function("c_datablock_put_double_array_1d")
          .whitelist_function("c_datablock_put_complex_array_1d")
+         .whitelist_function("c_datablock_put_string_array_1d")
          /* Replacing 1D arrays */
          .whitelist_function("c_datablock_replace_int_array_1d")
          .whitelist_function("c_datablock_replace_double_array_1d")
          .whitelist_function("c_datablock_replace_complex_array_1d")
-         /* TODO: Neglecting higher-dimensional arrays */
+         /* Metadata */
+         .whitelist_function("c_datablock_put_metadata")
+         .whitelist_function("c_datablock_replace_metadata")
+         .whitelist_function("c_datablock_get_metadata")
+         /* Access log */
+         .whitelist_function("c_datablock_get_log_count")
+         .whitelist_function("c_datablock_get_log_entry")
+         .whitelist_function("c_datablock_log_access")
+         .whitelist_function("c_datablock_print_log")
+         .whitelist_function("c_datablock_report_failures")
+         /* Multi-dimensional arrays */
+         .whitelist_function("c_datablock_get_array_ndim")
+         .whitelist_function("c_datablock_get_int_array_shape")
+         .whitelist_function("c_datablock_get_double_array_shape")
+         .whitelist_function("c_datablock_get_complex_array_shape")
+         .whitelist_function("c_datablock_get_int_array")
+         .whitelist_function("c_datablock_get_double_array")
+         .whitelist_function("c_datablock_get_complex_array")
+         .whitelist_function("c_datablock_put_int_array")
+         .whitelist_function("c_datablock_put_double_array")
+         .whitelist_function("c_datablock_put_complex_array")
+         .whitelist_function("c_datablock_replace_int_array")
+         .whitelist_function("c_datablock_replace_double_array")
+         .whitelist_function("c_datablock_replace_complex_array")
          .generate()
          .expect("Error generating bindings");
 
     bindings.write_to_file(manifest_path.join("src/_raw_cosmosis_bindings.rs"))
             .expect("Error writing bindings");
+
+    generate_section_names();
+}
+
+/// Sections whose constants `src/names.rs` defines itself, because the crate's own code uses them
+/// and must compile whatever the installed `section_names.txt` lists. Keep in sync with that file.
+const BUILTIN_SECTIONS: &[&str] = &["cosmological_parameters", "likelihoods", "priors"];
+
+/// Writes a `<NAME>_SECTION` constant for every section in CosmoSIS's `section_names.txt` to
+/// `$OUT_DIR/section_names.rs`, for `src/names.rs` to include. Uses the copy in `COSMOSIS_INC`
+/// if there is one, so that the constants track the installed CosmoSIS, and the vendored copy
+/// otherwise.
+fn generate_section_names() {
+    let manifest_path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let installed = env::var("COSMOSIS_INC").ok()
+                                            .map(|inc| Path::new(&inc).join("section_names.txt"))
+                                            .filter(|path| path.is_file());
+    let source = installed.unwrap_or_else(|| manifest_path.join("section_names.txt"));
+    println!("cargo:rerun-if-changed={}", source.to_str().unwrap());
+
+    let contents = fs::read_to_string(&source).expect("Error reading section_names.txt");
+    let mut generated = format!("// Generated by build.rs from {}\n", source.display());
+    let mut seen = Vec::new();
+    for line in contents.lines() {
+        let name = line.split('#').next().unwrap().trim().to_lowercase();
+        if name.is_empty() || seen.contains(&name) || BUILTIN_SECTIONS.contains(&name.as_str()) {
+            continue;
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+           || name.starts_with(|c: char| c.is_ascii_digit()) {
+            println!("cargo:warning=Skipping invalid section name {:?} in {}", name, source.display());
+            continue;
+        }
+        generated.push_str(&format!("pub const {}_SECTION: &str = {:?};\n", name.to_uppercase(), name));
+        seen.push(name);
+    }
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("section_names.rs");
+    fs::write(out_path, generated).expect("Error writing section names");
 }
@@ -0,0 +1,147 @@
+//! `#[derive(CosmosisSection)]`, re-exported by the `cosmosis` crate; see the documentation of
+//! `cosmosis::CosmosisSection` for the attributes it accepts.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Attribute, Data, DeriveInput, Expr, Fields, Ident, LitStr, Type};
+
+#[proc_macro_derive(CosmosisSection, attributes(cosmosis))]
+pub fn derive_cosmosis_section(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// The contents of the `#[cosmosis(...)]` attributes on a struct or field.
+#[derive(Default)]
+struct Attrs {
+    section: Option<LitStr>,
+    name: Option<LitStr>,
+    default: Option<Expr>,
+    optional: bool
+}
+
+impl Attrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = Attrs::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("cosmosis")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("section") {
+                    parsed.section = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("name") {
+                    parsed.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("default") {
+                    parsed.default = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("optional") {
+                    parsed.optional = true;
+                } else {
+                    return Err(meta.error("expected `section`, `name`, `default` or `optional`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(parsed)
+    }
+}
+
+/// One field of the struct, and where it lives in the `DataBlock`.
+struct Field<'a> {
+    ident: &'a Ident,
+    section: LitStr,
+    name: LitStr,
+    default: Option<Expr>,
+    optional: bool
+}
+
+fn is_option(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) => path.qself.is_none()
+                                && matches!(path.path.segments.last(), Some(segment) if segment.ident == "Option"),
+        _ => false
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident,
+                                                    "CosmosisSection can only be derived for structs with named fields"))
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "CosmosisSection can only be derived for structs"))
+    };
+
+    let struct_attrs = Attrs::parse(&input.attrs)?;
+    if struct_attrs.name.is_some() || struct_attrs.default.is_some() || struct_attrs.optional {
+        return Err(syn::Error::new_spanned(&input.ident,
+                                           "only `section` may be given as a struct-level cosmosis attribute"));
+    }
+
+    let mut specs = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let attrs = Attrs::parse(&field.attrs)?;
+        let section = attrs.section.or_else(|| struct_attrs.section.clone()).ok_or_else(|| {
+            syn::Error::new_spanned(ident, "no section given; add #[cosmosis(section = \"...\")] to the struct or field")
+        })?;
+        if attrs.optional && attrs.default.is_some() {
+            return Err(syn::Error::new_spanned(ident, "a field cannot be both `optional` and have a `default`"));
+        }
+        if attrs.optional && !is_option(&field.ty) {
+            return Err(syn::Error::new_spanned(&field.ty, "`optional` fields must have type `Option<_>`"));
+        }
+        let name = attrs.name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+        specs.push(Field { ident, section, name, default: attrs.default, optional: attrs.optional });
+    }
+
+    let ty = &input.ident;
+    let ty_name = ty.to_string();
+    let reads = specs.iter().map(|f| {
+        let (ident, section, name) = (f.ident, &f.section, &f.name);
+        let field = format!("{}::{}", ty_name, ident);
+        if f.optional {
+            quote! { #ident: ::cosmosis::section::read_optional_field(db, #section, #name, #field)? }
+        } else if let Some(ref default) = f.default {
+            quote! {
+                #ident: ::cosmosis::section::read_field_or_else(db, #section, #name, #field,
+                                                                || ::std::convert::From::from(#default))?
+            }
+        } else {
+            quote! { #ident: ::cosmosis::section::read_field(db, #section, #name, #field)? }
+        }
+    });
+    let writes = specs.iter().map(|f| {
+        let (ident, section, name) = (f.ident, &f.section, &f.name);
+        let field = format!("{}::{}", ty_name, ident);
+        if f.optional {
+            quote! {
+                if let ::std::option::Option::Some(ref value) = self.#ident {
+                    ::cosmosis::section::write_field(db, #section, #name, #field, value)?;
+                }
+            }
+        } else {
+            quote! { ::cosmosis::section::write_field(db, #section, #name, #field, &self.#ident)?; }
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::cosmosis::CosmosisSection for #ty #ty_generics #where_clause {
+            fn read(db: &::cosmosis::DataBlock) -> ::cosmosis::CosmosisResult<Self> {
+                ::std::result::Result::Ok(#ty {
+                    #(#reads,)*
+                })
+            }
+
+            fn write(&self, db: &mut ::cosmosis::DataBlock) -> ::cosmosis::CosmosisResult<()> {
+                #(#writes)*
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,148 @@
+//! Runs a CosmoSIS pipeline at the starting point of its values file, as the `test` sampler does.
+//!
+//! ```text
+//! cosmosis-run [--profile | --profile-json] [--repeat N] [--snapshots DIR] [--graph FILE]
+//!              pipeline.ini
+//! ```
+//!
+//! With `--profile`, a table of how long each module took, and how much it read and wrote, is
+//! printed after the run; `--profile-json` prints the same as JSON. `--repeat` runs the pipeline
+//! `N` times, to average out noisy timings. `--snapshots` saves the block after every module
+//! under `DIR`, with a diff of what each module changed; see `DebugSnapshots`. `--graph` writes
+//! which values each module reads and writes to `FILE`, as a Graphviz DOT graph, and prints any
+//! accesses which look out of order.
+
+extern crate cosmosis;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use cosmosis::{CosmosisResult, DebugSnapshots, Ini, Pipeline};
+
+const USAGE: &str = "usage: cosmosis-run [--profile | --profile-json] [--repeat N] [--snapshots DIR] [--graph FILE] pipeline.ini";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Profile {
+    Off,
+    Table,
+    Json
+}
+
+struct Arguments {
+    ini: String,
+    profile: Profile,
+    repeat: usize,
+    snapshots: Option<String>,
+    graph: Option<String>
+}
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut ini = None;
+    let mut profile = Profile::Off;
+    let mut repeat = 1;
+    let mut snapshots = None;
+    let mut graph = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => profile = Profile::Table,
+            "--profile-json" => profile = Profile::Json,
+            "--repeat" => {
+                repeat = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0)
+                             .ok_or_else(|| "--repeat needs a positive number of runs".to_string())?;
+            },
+            "--snapshots" => {
+                snapshots = Some(args.next().ok_or_else(|| "--snapshots needs a directory".to_string())?);
+            },
+            "--graph" => graph = Some(args.next().ok_or_else(|| "--graph needs a file name".to_string())?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ if ini.is_none() => ini = Some(arg),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let ini = ini.ok_or_else(|| USAGE.to_string())?;
+    Ok(Arguments { ini, profile, repeat, snapshots, graph })
+}
+
+fn run(args: &Arguments) -> CosmosisResult<()> {
+    let pipeline = run_pipeline(Pipeline::from_ini(&Ini::load(&args.ini)?)?, args)?;
+    match (args.profile, pipeline.profile()) {
+        (Profile::Table, Some(profile)) => print!("\n{}", profile),
+        (Profile::Json, Some(profile)) => println!("{}", profile.to_json()),
+        _ => ()
+    }
+    Ok(())
+}
+
+/// Runs `pipeline` as `args` ask, and returns it with its profile.
+fn run_pipeline(mut pipeline: Pipeline, args: &Arguments) -> CosmosisResult<Pipeline> {
+    let start = pipeline.parameters().start();
+    if let Some(ref file) = args.graph {
+        let flow = pipeline.data_flow(&start)?;
+        for warning in flow.warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+        fs::write(file, flow.to_dot())?;
+    }
+    if args.profile != Profile::Off {
+        pipeline = pipeline.with_profiling();
+    }
+    if let Some(ref dir) = args.snapshots {
+        pipeline = pipeline.with_debug_snapshots(DebugSnapshots::new(dir));
+    }
+    for _ in 0..args.repeat {
+        let result = pipeline.run(&start)?;
+        match result.rejected {
+            Some(ref reason) => println!("Rejected: {}", reason),
+            None => {
+                println!("Prior      = {}", result.log_prior);
+                println!("Likelihood = {}", result.log_likelihood);
+                println!("Posterior  = {}", result.log_posterior());
+            }
+        }
+    }
+    Ok(pipeline)
+}
+
+fn main() {
+    let args = parse_arguments().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(2);
+    });
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_pipeline, Arguments, Profile};
+    use cosmosis::{DataBlock, FunctionModule, ParameterSpace, Pipeline};
+    use std::{env, fs};
+
+    #[test]
+    fn test_graph_with_profile_and_snapshots() {
+        let dir = env::temp_dir().join(format!("cosmosis-run-test-{}", ::std::process::id()));
+        let graph = dir.join("pipeline.dot");
+        fs::create_dir_all(&dir).unwrap();
+        let mut pipeline = Pipeline::new(ParameterSpace::parse("[params]\nx = 0 1 2\n").unwrap());
+        pipeline.add_module(FunctionModule::new("like", |block: &mut DataBlock| {
+            let x = block.get::<f64>("params", "x")?;
+            block.put("likelihoods", "x_like", -x * x)
+        }));
+        let args = Arguments { ini: String::new(), profile: Profile::Table, repeat: 2,
+                               snapshots: Some(dir.join("snapshots").to_string_lossy().into_owned()),
+                               graph: Some(graph.to_string_lossy().into_owned()) };
+
+        let pipeline = run_pipeline(pipeline, &args).unwrap();
+        let profile = pipeline.profile().unwrap();
+        assert_eq!((profile.evaluations(), profile.get("like").unwrap().calls), (2, 2));
+        assert!(dir.join("snapshots/evaluation_2").is_dir());
+        assert!(!dir.join("snapshots/evaluation_3").exists());
+        assert!(fs::read_to_string(&graph).unwrap().contains("\"module:like\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+use std::io::{self, Read, Write};
+use std::os::raw;
+
+use super::{Complex, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, DEFAULT_METADATA_KEYS, Value,
+            datablock_type_t};
+use ndarray::NdArray;
+use npz::crc32;
+use value::SUPPORTED_TYPES;
+
+/// Identifies a binary-encoded block.
+const MAGIC: &[u8; 4] = b"CSDB";
+/// Bumped whenever the encoding changes incompatibly.
+const FORMAT_VERSION: u32 = 1;
+/// Magic, version, payload length and payload checksum.
+const HEADER_LEN: usize = 4 + 4 + 8 + 4;
+
+fn corrupt(message: String) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+        .with_reason(format!("Corrupt binary DataBlock: {}", message))
+}
+
+fn put_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, n: u64) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_f64(buf: &mut Vec<u8>, x: f64) {
+    buf.extend_from_slice(&x.to_bits().to_le_bytes());
+}
+
+fn put_complex(buf: &mut Vec<u8>, z: &Complex<f64>) {
+    put_f64(buf, z.re);
+    put_f64(buf, z.im);
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) -> CosmosisResult<()> {
+    if s.len() > u32::MAX as usize {
+        return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                   .with_reason(format!("String of {} bytes is too long to encode", s.len())));
+    }
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn put_value(buf: &mut Vec<u8>, value: &Value) -> CosmosisResult<()> {
+    put_u32(buf, value.datablock_type() as u32);
+    // The shape: a dimension count followed by the extent of each dimension.
+    match value.shape() {
+        Some(shape) => {
+            if shape.len() > u8::MAX as usize {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                           .with_reason(format!("Array of {} dimensions has too many to encode", shape.len())));
+            }
+            buf.push(shape.len() as u8);
+            shape.iter().for_each(|&n| put_u64(buf, n as u64));
+        },
+        None => buf.push(0)
+    }
+    match *value {
+        Value::Int(n) => buf.extend_from_slice(&n.to_le_bytes()),
+        Value::Bool(b) => buf.push(b as u8),
+        Value::Double(x) => put_f64(buf, x),
+        Value::Complex(ref z) => put_complex(buf, z),
+        Value::String(ref s) => put_str(buf, s)?,
+        Value::IntArray(ref v) => v.iter().for_each(|n| buf.extend_from_slice(&n.to_le_bytes())),
+        Value::DoubleArray(ref v) => v.iter().for_each(|&x| put_f64(buf, x)),
+        Value::ComplexArray(ref v) => v.iter().for_each(|z| put_complex(buf, z)),
+        Value::IntNdArray(ref a) => a.data().iter().for_each(|n| buf.extend_from_slice(&n.to_le_bytes())),
+        Value::DoubleNdArray(ref a) => a.data().iter().for_each(|&x| put_f64(buf, x)),
+        Value::ComplexNdArray(ref a) => a.data().iter().for_each(|z| put_complex(buf, z)),
+    }
+    Ok(())
+}
+
+/// Reads fields from a payload, failing rather than reading past its end.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> CosmosisResult<&'a [u8]> {
+        let end = self.pos.checked_add(len)
+                          .filter(|&end| end <= self.bytes.len())
+                          .ok_or_else(|| corrupt(format!("truncated at byte {} of the payload", self.pos)))?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> CosmosisResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> CosmosisResult<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> CosmosisResult<u64> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    fn int(&mut self) -> CosmosisResult<raw::c_int> {
+        Ok(self.u32()? as raw::c_int)
+    }
+
+    fn f64(&mut self) -> CosmosisResult<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    fn complex(&mut self) -> CosmosisResult<Complex<f64>> {
+        Ok(Complex { re: self.f64()?, im: self.f64()? })
+    }
+
+    fn string(&mut self) -> CosmosisResult<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        // CosmoSIS strings are NUL-terminated, so no valid block contains an interior NUL.
+        if bytes.contains(&0) {
+            return Err(corrupt(format!("NUL byte in string before byte {}", self.pos)));
+        }
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupt(format!("invalid UTF-8 before byte {}", self.pos)))
+    }
+
+    /// Reads a count of items which are each at least `item_size` bytes, checking it against the
+    /// bytes remaining so that a corrupt count cannot trigger a huge allocation.
+    fn count(&mut self, item_size: usize) -> CosmosisResult<usize> {
+        let count = self.u64()?;
+        let remaining = (self.bytes.len() - self.pos) as u64;
+        match count.checked_mul(item_size as u64) {
+            Some(size) if size <= remaining => Ok(count as usize),
+            _ => Err(corrupt(format!("count {} at byte {} exceeds the payload", count, self.pos - 8)))
+        }
+    }
+
+    fn array<T, F>(&mut self, item_size: usize, item: F) -> CosmosisResult<Vec<T>>
+        where F: Fn(&mut Self) -> CosmosisResult<T> {
+        let len = self.count(item_size)?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    /// Reads the extents of an array of `ndim` dimensions, then its elements.
+    fn ndarray<T, F>(&mut self, ndim: u8, item_size: usize, item: F) -> CosmosisResult<NdArray<T>>
+        where F: Fn(&mut Self) -> CosmosisResult<T> {
+        let start = self.pos;
+        let shape = (0..ndim).map(|_| self.u64().map(|n| n as usize)).collect::<CosmosisResult<Vec<_>>>()?;
+        // As in `count`, check the element count against the bytes remaining before allocating.
+        let remaining = self.bytes.len() - self.pos;
+        let len = shape.iter()
+                       .try_fold(1usize, |len, &n| len.checked_mul(n))
+                       .filter(|len| len.checked_mul(item_size).is_some_and(|size| size <= remaining))
+                       .ok_or_else(|| corrupt(format!("shape {:?} at byte {} exceeds the payload", shape, start)))?;
+        let data = (0..len).map(|_| item(self)).collect::<CosmosisResult<_>>()?;
+        NdArray::new(shape, data).map_err(|err| corrupt(err.reason.unwrap_or_default()))
+    }
+
+    fn value(&mut self) -> CosmosisResult<Value> {
+        let code = self.u32()?;
+        let ty = SUPPORTED_TYPES.iter()
+                                .copied()
+                                .find(|&ty| ty as u32 == code)
+                                .ok_or_else(|| corrupt(format!("unknown type code {}", code)))?;
+        let ndim = self.u8()?;
+        let valid_ndim = match ty {
+            datablock_type_t::DBT_INT1D | datablock_type_t::DBT_DOUBLE1D | datablock_type_t::DBT_COMPLEX1D => 1 == ndim,
+            datablock_type_t::DBT_INT2D | datablock_type_t::DBT_DOUBLE2D | datablock_type_t::DBT_COMPLEX2D => 2 == ndim,
+            datablock_type_t::DBT_INTND | datablock_type_t::DBT_DOUBLEND | datablock_type_t::DBT_COMPLEXND => ndim > 0,
+            _ => 0 == ndim
+        };
+        if !valid_ndim {
+            return Err(corrupt(format!("{} dimensions for a value of type {:?}", ndim, ty)));
+        }
+        Ok(match ty {
+            datablock_type_t::DBT_INT => Value::Int(self.int()?),
+            datablock_type_t::DBT_BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                b => return Err(corrupt(format!("invalid bool {}", b)))
+            },
+            datablock_type_t::DBT_DOUBLE => Value::Double(self.f64()?),
+            datablock_type_t::DBT_COMPLEX => Value::Complex(self.complex()?),
+            datablock_type_t::DBT_STRING => Value::String(self.string()?),
+            datablock_type_t::DBT_INT1D => Value::IntArray(self.array(4, Self::int)?),
+            datablock_type_t::DBT_DOUBLE1D => Value::DoubleArray(self.array(8, Self::f64)?),
+            datablock_type_t::DBT_COMPLEX1D => Value::ComplexArray(self.array(16, Self::complex)?),
+            datablock_type_t::DBT_INT2D | datablock_type_t::DBT_INTND =>
+                Value::IntNdArray(self.ndarray(ndim, 4, Self::int)?),
+            datablock_type_t::DBT_DOUBLE2D | datablock_type_t::DBT_DOUBLEND =>
+                Value::DoubleNdArray(self.ndarray(ndim, 8, Self::f64)?),
+            _ => Value::ComplexNdArray(self.ndarray(ndim, 16, Self::complex)?),
+        })
+    }
+}
+
+fn read_header(reader: &mut impl Read) -> CosmosisResult<(usize, u32)> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => corrupt("truncated header".to_string()),
+        _ => CosmosisError::from(err)
+    })?;
+    if &header[..4] != MAGIC {
+        return Err(corrupt("bad magic number".to_string()));
+    }
+    let mut decoder = Decoder { bytes: &header[4..], pos: 0 };
+    let version = decoder.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                   .with_reason(format!("Unsupported binary DataBlock version {} (expected {})", version,
+                                        FORMAT_VERSION)));
+    }
+    let len = decoder.u64()?;
+    if len > usize::MAX as u64 {
+        return Err(corrupt(format!("payload of {} bytes does not fit in memory", len)));
+    }
+    Ok((len as usize, decoder.u32()?))
+}
+
+impl DataBlock {
+    /// Writes this block, with its types, shapes and the metadata under `DEFAULT_METADATA_KEYS`,
+    /// in a compact binary encoding suitable for sending between processes.
+    ///
+    /// The encoding is a 20 byte header (the magic bytes `CSDB`, a format version, the payload
+    /// length and a CRC-32 of the payload) followed by the payload. All integers are
+    /// little-endian. Since the payload is length-prefixed, any number of blocks may be written
+    /// back to back to the same pipe and read off one at a time with `read_from`. Arrays of more
+    /// than 255 dimensions cannot be encoded.
+    pub fn write_to(&self, mut writer: impl Write) -> CosmosisResult<()> {
+        let mut payload = Vec::new();
+        let sections = self.sections();
+        put_u64(&mut payload, sections.len() as u64);
+        for section in &sections {
+            let names = self.names(section)?;
+            put_str(&mut payload, section)?;
+            put_u64(&mut payload, names.len() as u64);
+            for name in &names {
+                put_str(&mut payload, name)?;
+                put_value(&mut payload, &self.get_value(section, name)?)?;
+                let metadata = self.metadata_for(section, name, DEFAULT_METADATA_KEYS);
+                put_u64(&mut payload, metadata.len() as u64);
+                for (key, value) in metadata {
+                    put_str(&mut payload, &key)?;
+                    put_str(&mut payload, &value)?;
+                }
+            }
+        }
+
+        let mut header = MAGIC.to_vec();
+        put_u32(&mut header, FORMAT_VERSION);
+        put_u64(&mut header, payload.len() as u64);
+        put_u32(&mut header, crc32(&payload));
+        writer.write_all(&header)?;
+        writer.write_all(&payload)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads one block written by `write_to`, consuming exactly its bytes from `reader`.
+    /// Truncated or corrupt input is reported as a `DBS_LOGIC_ERROR`.
+    pub fn read_from(mut reader: impl Read) -> CosmosisResult<Self> {
+        let (len, checksum) = read_header(&mut reader)?;
+        // Read through `take` rather than allocating `len` bytes up front, in case `len` is bogus.
+        let mut payload = Vec::new();
+        reader.by_ref().take(len as u64).read_to_end(&mut payload)?;
+        if payload.len() != len {
+            return Err(corrupt(format!("expected a {} byte payload but found {} bytes", len, payload.len())));
+        }
+        if crc32(&payload) != checksum {
+            return Err(corrupt("checksum mismatch".to_string()));
+        }
+
+        let mut decoder = Decoder { bytes: &payload, pos: 0 };
+        let mut db = DataBlock::new();
+        // Every section holds at least its name length and entry count.
+        for _ in 0..decoder.count(12)? {
+            let section = decoder.string()?;
+            for _ in 0..decoder.count(4)? {
+                let name = decoder.string()?;
+                db.put_value(&section, &name, &decoder.value()?)?;
+                for _ in 0..decoder.count(8)? {
+                    let key = decoder.string()?;
+                    db.put_metadata(&section, &name, &key, &decoder.string()?)?;
+                }
+            }
+        }
+        if decoder.pos != payload.len() {
+            return Err(corrupt(format!("{} unexpected trailing bytes", payload.len() - decoder.pos)));
+        }
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HEADER_LEN, crc32};
+    use std::f64;
+    use std::os::raw;
+    use {Complex, DataBlock, DATABLOCK_STATUS, NdArray, Tolerance};
+
+    fn example() -> DataBlock {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).unwrap();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "flat", true).unwrap();
+        db.put("cosmological_parameters", "z_c", Complex { re: 1.0, im: -0.5 }).unwrap();
+        db.put::<str, _>("cosmological_parameters", "label", "Planck").unwrap();
+        db.put::<[raw::c_int], _>("distances", "bins", &[1, -2][..]).unwrap();
+        db.put::<[f64], _>("distances", "z", &[0.0, f64::NAN, -0.0][..]).unwrap();
+        db.put::<[Complex<f64>], _>("distances", "phase", &[Complex { re: 0.0, im: 1.0 }][..]).unwrap();
+        db.put("distances", "cov", NdArray::new(vec![2, 2], vec![1.0, 0.5, 0.5, 2.0]).unwrap()).unwrap();
+        db.put_metadata("distances", "z", "unit", "redshift").unwrap();
+        db
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let db = example();
+        let mut stream = Vec::new();
+        db.write_to(&mut stream).unwrap();
+        db.write_to(&mut stream).unwrap();
+
+        let mut reader = &stream[..];
+        for _ in 0..2 {
+            let loaded = DataBlock::read_from(&mut reader).unwrap();
+            assert_blocks_close!(db, loaded, Tolerance::exact());
+            assert_eq!(loaded.get_metadata("distances", "z", "unit").unwrap(), "redshift");
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_binary_corrupt_input() {
+        let mut encoded = Vec::new();
+        example().write_to(&mut encoded).unwrap();
+
+        for len in 0..encoded.len() {
+            let err = DataBlock::read_from(&encoded[..len]).unwrap_err();
+            assert_eq!(err.kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        }
+
+        // Flip each payload byte, fixing up the checksum so that the decoder itself sees the damage.
+        // Decoding may succeed or fail, but must never panic.
+        for i in HEADER_LEN..encoded.len() {
+            let mut corrupt = encoded.clone();
+            corrupt[i] ^= 0xff;
+            let checksum = crc32(&corrupt[HEADER_LEN..]);
+            corrupt[HEADER_LEN - 4..HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+            let _ = DataBlock::read_from(&corrupt[..]);
+        }
+
+        let mut bad_checksum = encoded.clone();
+        bad_checksum[HEADER_LEN] ^= 1;
+        assert!(DataBlock::read_from(&bad_checksum[..]).is_err());
+    }
+
+    #[test]
+    fn test_binary_too_many_dimensions() {
+        let mut db = DataBlock::new();
+        db.put("theory", "deep", NdArray::new(vec![1; 256], vec![1.0]).unwrap()).unwrap();
+        let err = db.write_to(Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("256 dimensions"), "{}", err);
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+use super::{CosmosisResult, DataBlock, Value};
+use expression::{Expression, ExpressionError, Reference};
+
+/// A condition every sampled point must meet, e.g. `cosmological_parameters--omega_b <
+/// cosmological_parameters--omega_m`. Points which violate it are rejected before any module runs.
+///
+/// A constraint is an `Expression` which is true when non-zero. Since it is checked against the
+/// whole block rather than one section, every parameter it refers to must be written
+/// `section--name`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraint {
+    expression: Expression
+}
+
+impl Constraint {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let expression = Expression::parse(source.trim())?;
+        if let Some(reference) = expression.references().into_iter().find(|reference| reference.section.is_none()) {
+            return Err(ExpressionError {
+                expression: expression.source().to_string(),
+                position: None,
+                message: format!("Parameter {} needs a section, as in section--{}", reference, reference.name)
+            });
+        }
+        Ok(Constraint { expression })
+    }
+
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+
+    /// Evaluates the constraint on the parameters in `db`. A NaN result counts as a violation,
+    /// while a parameter which is missing or not a number is an error.
+    pub fn is_satisfied(&self, db: &DataBlock) -> CosmosisResult<bool> {
+        let lookup = |reference: &Reference| {
+            let section = reference.section.as_ref()?;
+            match db.get_value(section.as_str(), reference.name.as_str()).ok()? {
+                Value::Double(x) => Some(x),
+                Value::Int(n) => Some(f64::from(n)),
+                _ => None
+            }
+        };
+        let value = self.expression.evaluate(&lookup)?;
+        Ok(value != 0.0 && !value.is_nan())
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+/// The `constraints` option of a pipeline: the conditions a point must meet to be run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Constraints {
+    constraints: Vec<Constraint>
+}
+
+impl Constraints {
+    /// Parses one constraint per line, so that a long list can use ini continuation lines.
+    /// Blank lines are skipped.
+    pub fn parse(option: &str) -> Result<Self, ExpressionError> {
+        let constraints = option.lines().filter(|line| !line.trim().is_empty())
+                                .map(Constraint::parse)
+                                .collect::<Result<Vec<_>, _>>()?;
+        Ok(Constraints { constraints })
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// Returns the first constraint which the point in `db` violates, if any.
+    pub fn check(&self, db: &DataBlock) -> CosmosisResult<Option<&Constraint>> {
+        for constraint in &self.constraints {
+            if !constraint.is_satisfied(db)? {
+                return Ok(Some(constraint));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Constraint, Constraints};
+    use {DataBlock, DATABLOCK_STATUS};
+
+    #[test]
+    fn test_check() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "omega_b", 0.05).unwrap();
+
+        let constraints = Constraints::parse("cosmological_parameters--omega_b < cosmological_parameters--omega_m\n\
+                                              \n   cosmological_parameters--omega_m + cosmological_parameters--omega_b <= 0.32\n").unwrap();
+        assert_eq!(constraints.constraints().len(), 2);
+        let violated = constraints.check(&db).unwrap().unwrap();
+        assert_eq!(violated.to_string(),
+                   "cosmological_parameters--omega_m + cosmological_parameters--omega_b <= 0.32");
+
+        db.overwrite("cosmological_parameters", "omega_m", 0.25).unwrap();
+        assert!(constraints.check(&db).unwrap().is_none());
+        assert!(Constraints::default().check(&db).unwrap().is_none());
+
+        let missing = Constraint::parse("cosmological_parameters--h0 > 0.5").unwrap();
+        let err = missing.is_satisfied(&db).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(err.to_string().contains("Unknown parameter cosmological_parameters--h0"), "{}", err);
+    }
+
+    #[test]
+    fn test_unqualified_reference() {
+        let err = Constraint::parse("omega_m > 0").unwrap_err();
+        assert!(err.message.contains("section--omega_m"), "{}", err);
+        assert!(Constraints::parse("a--x >").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+use log::{LogEntry, LogOperation};
+use snapshot::PARAMETERS_STAGE;
+
+/// The `(section, name)` pairs one stage of a pipeline read and wrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StageAccesses {
+    /// The module's name, or `PARAMETERS_STAGE` for the sampler's writes before any module ran.
+    pub stage: String,
+    /// Values the stage read successfully.
+    pub reads: BTreeSet<(String, String)>,
+    /// Values the stage wrote or replaced.
+    pub writes: BTreeSet<(String, String)>
+}
+
+/// A suspicious access found while building a `DataFlow`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataFlowWarning {
+    /// A module tried to read a value before any earlier stage wrote it. It may have used a
+    /// default, or it may depend on a module which runs after it.
+    ReadBeforeWrite { module: String, section: String, name: String },
+    /// A module replaced a value written by an earlier stage.
+    Overwritten { section: String, name: String, writer: String, by: String }
+}
+
+impl fmt::Display for DataFlowWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataFlowWarning::ReadBeforeWrite { ref module, ref section, ref name } =>
+                write!(f, "{} reads {}/{} before any module writes it", module, section, name),
+            DataFlowWarning::Overwritten { ref section, ref name, ref writer, ref by } =>
+                write!(f, "{} overwrites {}/{}, written by {}", by, section, name, writer)
+        }
+    }
+}
+
+/// Which values each module of a pipeline reads and writes, as recorded in a block's access log
+/// during one evaluation. See `Pipeline::data_flow`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DataFlow {
+    stages: Vec<StageAccesses>,
+    warnings: Vec<DataFlowWarning>
+}
+
+impl DataFlow {
+    /// Attributes each access in `log` to the module named by the `MODULE-START` entry before it,
+    /// or to `PARAMETERS_STAGE` if there is none. Sections and names are compared
+    /// case-insensitively, as CosmoSIS does.
+    pub fn from_log(log: &[LogEntry]) -> Self {
+        let mut flow = DataFlow::default();
+        let mut stage = flow.stage_index(PARAMETERS_STAGE);
+        let mut writers: BTreeMap<(String, String), usize> = BTreeMap::new();
+        let mut warned = BTreeSet::new();
+
+        for entry in log {
+            let key = (entry.section.to_lowercase(), entry.name.to_lowercase());
+            match entry.operation {
+                LogOperation::ModuleStart => stage = flow.stage_index(&entry.section),
+                LogOperation::Read | LogOperation::ReadDefault | LogOperation::ReadFail => {
+                    if !writers.contains_key(&key) && warned.insert((stage, key.clone())) {
+                        flow.warnings.push(DataFlowWarning::ReadBeforeWrite {
+                            module: flow.stages[stage].stage.clone(), section: key.0.clone(), name: key.1.clone()
+                        });
+                    }
+                    if entry.operation == LogOperation::Read {
+                        flow.stages[stage].reads.insert(key);
+                    }
+                },
+                LogOperation::Write | LogOperation::Replace => {
+                    if let Some(&writer) = writers.get(&key) {
+                        if writer != stage {
+                            flow.warnings.push(DataFlowWarning::Overwritten {
+                                section: key.0.clone(), name: key.1.clone(),
+                                writer: flow.stages[writer].stage.clone(), by: flow.stages[stage].stage.clone()
+                            });
+                        }
+                    }
+                    writers.insert(key.clone(), stage);
+                    flow.stages[stage].writes.insert(key);
+                },
+                LogOperation::Delete => { writers.remove(&key); },
+                LogOperation::Clear => writers.clear(),
+                _ => ()
+            }
+        }
+
+        if flow.stages[0].reads.is_empty() && flow.stages[0].writes.is_empty() {
+            flow.stages.remove(0);
+        }
+        flow
+    }
+
+    fn stage_index(&mut self, stage: &str) -> usize {
+        match self.stages.iter().position(|accesses| accesses.stage == stage) {
+            Some(index) => index,
+            None => {
+                self.stages.push(StageAccesses { stage: stage.to_string(), ..StageAccesses::default() });
+                self.stages.len() - 1
+            }
+        }
+    }
+
+    /// The stages in the order they ran, starting with `PARAMETERS_STAGE` if it wrote anything.
+    pub fn stages(&self) -> &[StageAccesses] {
+        &self.stages
+    }
+
+    pub fn warnings(&self) -> &[DataFlowWarning] {
+        &self.warnings
+    }
+
+    /// The data-flow graph in Graphviz's DOT language, with a box for each module, an ellipse for
+    /// each value grouped by section, and an edge for each read and write. Render it with e.g.
+    /// `dot -Tsvg pipeline.dot -o pipeline.svg`.
+    pub fn to_dot(&self) -> String {
+        let mut sections: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for stage in &self.stages {
+            for (section, name) in stage.reads.iter().chain(stage.writes.iter()) {
+                sections.entry(section.as_str()).or_default().insert(name.as_str());
+            }
+        }
+
+        // Writing to a String can't fail.
+        let mut dot = String::new();
+        dot.push_str("digraph pipeline {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+        for stage in &self.stages {
+            let _ = writeln!(dot, "    {} [label={}, shape=box, style=filled, fillcolor=lightgrey];",
+                             quote(&stage_id(&stage.stage)), quote(&stage.stage));
+        }
+        for (i, (section, names)) in sections.iter().enumerate() {
+            let _ = writeln!(dot, "    subgraph cluster_{} {{\n        label={};", i, quote(section));
+            for name in names {
+                let _ = writeln!(dot, "        {} [label={}];", quote(&value_id(section, name)), quote(name));
+            }
+            dot.push_str("    }\n");
+        }
+        for stage in &self.stages {
+            for (section, name) in &stage.reads {
+                let _ = writeln!(dot, "    {} -> {};", quote(&value_id(section, name)), quote(&stage_id(&stage.stage)));
+            }
+            for (section, name) in &stage.writes {
+                let _ = writeln!(dot, "    {} -> {};", quote(&stage_id(&stage.stage)), quote(&value_id(section, name)));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn stage_id(stage: &str) -> String {
+    format!("module:{}", stage)
+}
+
+fn value_id(section: &str, name: &str) -> String {
+    format!("value:{}/{}", section, name)
+}
+
+/// A DOT string literal.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataFlow, DataFlowWarning};
+    use {DataBlock, LogOperation};
+
+    #[test]
+    fn test_from_log() {
+        let mut block = DataBlock::new();
+        block.put("params", "x", 1.0).unwrap();
+        block.log_access(LogOperation::ModuleStart.as_str(), "theory", "").unwrap();
+        block.get::<f64>("Params", "X").unwrap();
+        block.put("theory", "y", 2.0).unwrap();
+        block.overwrite("params", "x", 3.0).unwrap();
+        assert!(block.get::<f64>("data", "z").is_err());
+        block.log_access(LogOperation::ModuleStart.as_str(), "like", "").unwrap();
+        block.get::<f64>("theory", "y").unwrap();
+        block.put("likelihoods", "like_like", 0.0).unwrap();
+
+        let flow = DataFlow::from_log(&block.access_log().unwrap());
+        let stages: Vec<_> = flow.stages().iter().map(|stage| stage.stage.as_str()).collect();
+        assert_eq!(stages, vec!["parameters", "theory", "like"]);
+        assert!(flow.stages()[1].reads.contains(&("params".to_string(), "x".to_string())));
+        assert_eq!(flow.warnings(), &[
+            DataFlowWarning::Overwritten { section: "params".to_string(), name: "x".to_string(),
+                                           writer: "parameters".to_string(), by: "theory".to_string() },
+            DataFlowWarning::ReadBeforeWrite { module: "theory".to_string(), section: "data".to_string(),
+                                               name: "z".to_string() }
+        ][..]);
+        assert_eq!(flow.warnings()[1].to_string(), "theory reads data/z before any module writes it");
+
+        let dot = flow.to_dot();
+        assert!(dot.starts_with("digraph pipeline {\n"), "{}", dot);
+        assert!(dot.contains("    \"module:theory\" -> \"value:theory/y\";\n"), "{}", dot);
+        assert!(dot.contains("    \"value:theory/y\" -> \"module:like\";\n"), "{}", dot);
+        assert!(!dot.contains("data/z"), "{}", dot);
+    }
+}
@@ -0,0 +1,326 @@
+use std::fmt;
+
+use super::{Complex, DataBlock, Value, datablock_type_t};
+
+/// Number of differing array elements listed per entry in a `BlockDiff` report.
+const REPORT_MAX_ELEMENTS: usize = 5;
+
+/// Tolerances for comparing floating-point (and complex) entries. Two numbers `a` and `b` are
+/// considered close if `|a - b| <= abs + rel * max(|a|, |b|)`. Two NaNs are considered close, so
+/// that a module which consistently produces NaN does not show up as a regression.
+///
+/// Integers, booleans and strings are always compared exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    pub abs: f64,
+    pub rel: f64
+}
+
+impl Tolerance {
+    pub fn new(abs: f64, rel: f64) -> Self {
+        Tolerance { abs, rel }
+    }
+
+    /// Only bit-for-bit equal numbers (or two NaNs) are close.
+    pub fn exact() -> Self {
+        Tolerance { abs: 0.0, rel: 0.0 }
+    }
+
+    pub fn absolute(abs: f64) -> Self {
+        Tolerance { abs, rel: 0.0 }
+    }
+
+    pub fn relative(rel: f64) -> Self {
+        Tolerance { abs: 0.0, rel }
+    }
+
+    pub fn is_close(&self, a: f64, b: f64) -> bool {
+        if a == b || (a.is_nan() && b.is_nan()) {
+            return true;
+        }
+        (a - b).abs() <= self.abs + self.rel * a.abs().max(b.abs())
+    }
+
+    pub fn is_close_complex(&self, a: Complex<f64>, b: Complex<f64>) -> bool {
+        if (a.re == b.re || (a.re.is_nan() && b.re.is_nan())) &&
+           (a.im == b.im || (a.im.is_nan() && b.im.is_nan())) {
+            return true;
+        }
+        let modulus = |z: Complex<f64>| z.re.hypot(z.im);
+        let delta = Complex { re: a.re - b.re, im: a.im - b.im };
+        modulus(delta) <= self.abs + self.rel * modulus(a).max(modulus(b))
+    }
+}
+
+/// The same defaults as `numpy.isclose`: `abs = 1e-8`, `rel = 1e-5`.
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance { abs: 1e-8, rel: 1e-5 }
+    }
+}
+
+/// A single array element which differs between two blocks. Elements of multi-dimensional arrays
+/// are indexed in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElementDiff {
+    pub index: usize,
+    pub left: Value,
+    pub right: Value
+}
+
+/// One difference between two `DataBlock`s. "Added" and "removed" are relative to the block `diff`
+/// was called on: an entry only present in the other block is `KeyAdded`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    SectionAdded(String),
+    SectionRemoved(String),
+    KeyAdded { section: String, name: String },
+    KeyRemoved { section: String, name: String },
+    TypeChanged { section: String, name: String, left: datablock_type_t, right: datablock_type_t },
+    LengthChanged { section: String, name: String, left: usize, right: usize },
+    ValueChanged { section: String, name: String, left: Value, right: Value },
+    ElementsChanged { section: String, name: String, len: usize, elements: Vec<ElementDiff> }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Difference::SectionAdded(ref section) => write!(f, "+ [{}] (section added)", section),
+            Difference::SectionRemoved(ref section) => write!(f, "- [{}] (section removed)", section),
+            Difference::KeyAdded { ref section, ref name } => write!(f, "+ {}/{} (added)", section, name),
+            Difference::KeyRemoved { ref section, ref name } => write!(f, "- {}/{} (removed)", section, name),
+            Difference::TypeChanged { ref section, ref name, left, right } =>
+                write!(f, "~ {}/{}: type {:?} -> {:?}", section, name, left, right),
+            Difference::LengthChanged { ref section, ref name, left, right } =>
+                write!(f, "~ {}/{}: length {} -> {}", section, name, left, right),
+            Difference::ValueChanged { ref section, ref name, ref left, ref right } =>
+                write!(f, "~ {}/{}: {} -> {}", section, name, left, right),
+            Difference::ElementsChanged { ref section, ref name, len, ref elements } => {
+                write!(f, "~ {}/{}: {} of {} elements differ", section, name, elements.len(), len)?;
+                for element in elements.iter().take(REPORT_MAX_ELEMENTS) {
+                    write!(f, "\n    [{}] {} -> {}", element.index, element.left, element.right)?;
+                }
+                if elements.len() > REPORT_MAX_ELEMENTS {
+                    write!(f, "\n    ... and {} more", elements.len() - REPORT_MAX_ELEMENTS)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The result of `DataBlock::diff`: every difference found, in section and name order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockDiff {
+    pub differences: Vec<Difference>
+}
+
+impl BlockDiff {
+    /// Whether the two blocks were equal within tolerance.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.differences.len()
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, Difference> {
+        self.differences.iter()
+    }
+}
+
+/// A readable report with one line per difference (plus a few lines per differing array).
+impl fmt::Display for BlockDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.differences.is_empty() {
+            return write!(f, "no differences");
+        }
+        for (i, difference) in self.differences.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+fn sorted<T: Ord>(mut v: Vec<T>) -> Vec<T> {
+    v.sort();
+    v
+}
+
+fn diff_elements<T, C>(left: &[T], right: &[T], wrap: fn(T) -> Value, close: C) -> Vec<ElementDiff>
+    where T: Copy,
+          C: Fn(T, T) -> bool {
+    left.iter().zip(right.iter()).enumerate()
+        .filter(|&(_, (&l, &r))| !close(l, r))
+        .map(|(index, (&l, &r))| ElementDiff { index, left: wrap(l), right: wrap(r) })
+        .collect()
+}
+
+fn diff_values(section: &str, name: &str, left: Value, right: Value, tolerance: Tolerance) -> Option<Difference> {
+    if let (Some(left_len), Some(right_len)) = (left.len(), right.len()) {
+        if left_len != right_len {
+            return Some(Difference::LengthChanged {
+                section: section.to_string(), name: name.to_string(), left: left_len, right: right_len
+            });
+        }
+    }
+
+    let elements = match (&left, &right) {
+        (&Value::Double(l), &Value::Double(r)) if tolerance.is_close(l, r) => return None,
+        (&Value::Complex(l), &Value::Complex(r)) if tolerance.is_close_complex(l, r) => return None,
+        (Value::IntArray(l), Value::IntArray(r)) =>
+            diff_elements(l, r, Value::Int, |a, b| a == b),
+        (Value::DoubleArray(l), Value::DoubleArray(r)) =>
+            diff_elements(l, r, Value::Double, |a, b| tolerance.is_close(a, b)),
+        (Value::ComplexArray(l), Value::ComplexArray(r)) =>
+            diff_elements(l, r, Value::Complex, |a, b| tolerance.is_close_complex(a, b)),
+        (Value::IntNdArray(l), Value::IntNdArray(r)) if l.shape() == r.shape() =>
+            diff_elements(l.data(), r.data(), Value::Int, |a, b| a == b),
+        (Value::DoubleNdArray(l), Value::DoubleNdArray(r)) if l.shape() == r.shape() =>
+            diff_elements(l.data(), r.data(), Value::Double, |a, b| tolerance.is_close(a, b)),
+        (Value::ComplexNdArray(l), Value::ComplexNdArray(r)) if l.shape() == r.shape() =>
+            diff_elements(l.data(), r.data(), Value::Complex, |a, b| tolerance.is_close_complex(a, b)),
+        (l, r) if l == r => return None,
+        _ => {
+            return Some(Difference::ValueChanged {
+                section: section.to_string(), name: name.to_string(), left, right
+            });
+        }
+    };
+
+    if elements.is_empty() {
+        None
+    } else {
+        Some(Difference::ElementsChanged {
+            section: section.to_string(), name: name.to_string(), len: left.len().unwrap_or(0), elements
+        })
+    }
+}
+
+impl DataBlock {
+    /// Compares this block against `other`, reporting added and removed sections and keys, type and
+    /// length changes, and values which differ beyond `tolerance` (element-wise for arrays).
+    ///
+    /// Entries of types without a `Value` representation are compared by type only.
+    pub fn diff(&self, other: &DataBlock, tolerance: Tolerance) -> BlockDiff {
+        let mut differences = Vec::new();
+        let left_sections = sorted(self.sections());
+        let right_sections = sorted(other.sections());
+
+        for section in &left_sections {
+            if !right_sections.contains(section) {
+                differences.push(Difference::SectionRemoved(section.clone()));
+                continue;
+            }
+
+            let left_names = sorted(self.names(section).unwrap_or_default());
+            let right_names = sorted(other.names(section).unwrap_or_default());
+            for name in &left_names {
+                if !right_names.contains(name) {
+                    differences.push(Difference::KeyRemoved { section: section.clone(), name: name.clone() });
+                    continue;
+                }
+
+                let left_type = self.get_type(section, name).unwrap_or(datablock_type_t::DBT_UNKNOWN);
+                let right_type = other.get_type(section, name).unwrap_or(datablock_type_t::DBT_UNKNOWN);
+                if left_type != right_type {
+                    differences.push(Difference::TypeChanged {
+                        section: section.clone(), name: name.clone(), left: left_type, right: right_type
+                    });
+                    continue;
+                }
+
+                if let (Ok(left), Ok(right)) = (self.get_value(section, name), other.get_value(section, name)) {
+                    differences.extend(diff_values(section, name, left, right, tolerance));
+                }
+            }
+            for name in right_names.iter().filter(|name| !left_names.contains(name)) {
+                differences.push(Difference::KeyAdded { section: section.clone(), name: name.clone() });
+            }
+        }
+        for section in right_sections.iter().filter(|section| !left_sections.contains(section)) {
+            differences.push(Difference::SectionAdded(section.clone()));
+        }
+
+        BlockDiff { differences }
+    }
+}
+
+/// Asserts that two `DataBlock`s are equal within a `Tolerance` (`Tolerance::default()` if not
+/// given), panicking with a readable report of every difference otherwise.
+#[macro_export]
+macro_rules! assert_blocks_close {
+    ( $left:expr, $right:expr ) => {
+        assert_blocks_close!($left, $right, $crate::Tolerance::default())
+    };
+    ( $left:expr, $right:expr, $tolerance:expr ) => {{
+        let diff = $crate::DataBlock::diff(&$left, &$right, $tolerance);
+        if !diff.is_empty() {
+            panic!("assertion failed: DataBlocks `{}` and `{}` are not close:\n{}",
+                   stringify!($left), stringify!($right), diff);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataBlock, Difference, ElementDiff, Tolerance, Value, datablock_type_t};
+    use std::os::raw;
+
+    fn make_block() -> DataBlock {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).unwrap();
+        db.put::<[f64], _>("distances", "d_a", &[0.0, 100.0, 200.0, 300.0][..]).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_diff_within_tolerance() {
+        let left = make_block();
+        let mut right = make_block();
+        right.overwrite("cosmological_parameters", "omega_m", 0.3 + 1e-9).unwrap();
+        right.overwrite::<[f64], _>("distances", "d_a", &[0.0, 100.0, 200.001, 300.0][..]).unwrap();
+
+        assert!(left.diff(&right, Tolerance::relative(1e-4)).is_empty());
+        assert_blocks_close!(left, right, Tolerance::relative(1e-4));
+
+        let diff = left.diff(&right, Tolerance::relative(1e-8));
+        assert_eq!(diff.differences, vec![Difference::ElementsChanged {
+            section: "distances".to_string(), name: "d_a".to_string(), len: 4,
+            elements: vec![ElementDiff { index: 2, left: Value::Double(200.0), right: Value::Double(200.001) }]
+        }]);
+    }
+
+    #[test]
+    fn test_diff_structure() {
+        let left = make_block();
+        let mut right = DataBlock::new();
+        right.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        right.put("cosmological_parameters", "n_massive", 3.0).unwrap();
+        right.put::<[f64], _>("distances", "d_a", &[0.0, 1.0][..]).unwrap();
+        right.put("likelihoods", "shear_like", -10.0).unwrap();
+
+        let diff = left.diff(&right, Tolerance::default());
+        assert_eq!(diff.differences, vec![
+            Difference::TypeChanged { section: "cosmological_parameters".to_string(), name: "n_massive".to_string(),
+                                      left: datablock_type_t::DBT_INT, right: datablock_type_t::DBT_DOUBLE },
+            Difference::LengthChanged { section: "distances".to_string(), name: "d_a".to_string(), left: 4, right: 2 },
+            Difference::SectionAdded("likelihoods".to_string()),
+        ]);
+        assert!(diff.to_string().contains("+ [likelihoods] (section added)"));
+    }
+
+    #[test]
+    #[should_panic(expected = "omega_m: 0.3 -> 0.4")]
+    fn test_assert_blocks_close() {
+        let left = make_block();
+        let mut right = make_block();
+        right.overwrite("cosmological_parameters", "omega_m", 0.4).unwrap();
+        assert_blocks_close!(left, right);
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::os::raw;
+use std::path::Path;
+
+use super::{Complex, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, Value, datablock_type_t};
+use ndarray::NdArray;
+use value::{parse_type_tag, type_tag};
+
+/// Name of the per-section file holding all scalar values.
+const SCALAR_FILE: &str = "values.txt";
+
+fn io_error(path: &Path, err: io::Error) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(format!("{}: {}", path.display(), err))
+}
+
+fn parse_error(path: &Path, line: usize, message: String) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+        .with_reason(format!("{}:{}: {}", path.display(), line, message))
+}
+
+/// Fails with `DBS_LOGIC_ERROR` if a section or array name, which becomes a directory or file
+/// name, could point outside the directory being written.
+fn check_path_component(name: &str) -> CosmosisResult<()> {
+    if name.contains('/') || name.contains('\\') || name.contains("..") {
+        return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                   .with_reason(format!("{:?} cannot be used as a file name", name)));
+    }
+    Ok(())
+}
+
+/// Formats a double the way Python's `repr` would, so that it always reads back as a double.
+fn format_double(x: f64) -> String {
+    if x.is_nan() {
+        "nan".to_string()
+    } else {
+        format!("{:?}", x)
+    }
+}
+
+fn format_complex(z: Complex<f64>) -> String {
+    let im = format_double(z.im);
+    let sign = if im.starts_with('-') { "" } else { "+" };
+    format!("({}{}{}j)", format_double(z.re), sign, im)
+}
+
+fn quote_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('\'');
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn unquote_string(s: &str) -> String {
+    let mut unquoted = String::with_capacity(s.len());
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('r') => unquoted.push('\r'),
+            Some('t') => unquoted.push('\t'),
+            Some(c) => unquoted.push(c),
+            None => unquoted.push('\\')
+        }
+    }
+    unquoted
+}
+
+/// Parses Python-style complex literals: `(1+2j)`, `(1.5-0j)`, `2j`.
+fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    let s = s.trim();
+    let s = if s.starts_with('(') && s.ends_with(')') { &s[1..s.len() - 1] } else { s };
+    if !s.ends_with('j') {
+        return None;
+    }
+    let s = &s[..s.len() - 1];
+
+    // The imaginary part starts at the last sign which is neither leading nor part of an exponent
+    let split = s.char_indices()
+                 .rev()
+                 .find(|&(i, c)| (c == '+' || c == '-') && i > 0 &&
+                                 !s[..i].ends_with('e') && !s[..i].ends_with('E'))
+                 .map(|(i, _)| i);
+    match split {
+        Some(i) => Some(Complex { re: s[..i].parse().ok()?, im: s[i..].parse().ok()? }),
+        None => Some(Complex { re: 0.0, im: s.parse().ok()? })
+    }
+}
+
+/// Parses a scalar written by `format_scalar` (or by Python's `repr`). Anything which is not a
+/// recognizable literal is read as an unquoted string.
+fn parse_scalar(s: &str) -> Value {
+    let s = s.trim();
+    if s == "True" || s == "False" {
+        return Value::Bool(s == "True");
+    }
+    if s.len() >= 2 && ((s.starts_with('\'') && s.ends_with('\'')) || (s.starts_with('"') && s.ends_with('"'))) {
+        return Value::String(unquote_string(s));
+    }
+    if let Ok(n) = s.parse::<raw::c_int>() {
+        return Value::Int(n);
+    }
+    if let Ok(x) = s.parse::<f64>() {
+        return Value::Double(x);
+    }
+    if let Some(z) = parse_complex(s) {
+        return Value::Complex(z);
+    }
+    Value::String(s.to_string())
+}
+
+fn format_scalar(value: &Value) -> Option<String> {
+    match *value {
+        Value::Int(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(if b { "True" } else { "False" }.to_string()),
+        Value::Double(x) => Some(format_double(x)),
+        Value::Complex(z) => Some(format_complex(z)),
+        Value::String(ref s) => Some(quote_string(s)),
+        _ => None
+    }
+}
+
+fn write_array(path: &Path, name: &str, value: &Value) -> io::Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    writeln!(file, "# name = {}", name)?;
+    writeln!(file, "# type = {}", type_tag(value.datablock_type()))?;
+    match *value {
+        Value::IntArray(ref v) => for n in v { writeln!(file, "{}", n)?; },
+        Value::DoubleArray(ref v) => for x in v { writeln!(file, "{}", format_double(*x))?; },
+        Value::ComplexArray(ref v) => for z in v { writeln!(file, "{}", format_complex(*z))?; },
+        Value::IntNdArray(ref a) => write_rows(&mut file, a, |n| n.to_string())?,
+        Value::DoubleNdArray(ref a) => write_rows(&mut file, a, |x| format_double(*x))?,
+        Value::ComplexNdArray(ref a) => write_rows(&mut file, a, |z| format_complex(*z))?,
+        _ => unreachable!("write_array called with a scalar")
+    }
+    file.flush()
+}
+
+/// Writes a `# shape = ...` header, then one line per run of the last dimension, as
+/// `numpy.savetxt` writes matrices.
+fn write_rows<T, F>(file: &mut impl Write, array: &NdArray<T>, format: F) -> io::Result<()>
+    where F: Fn(&T) -> String {
+    let shape: Vec<String> = array.shape().iter().map(|n| n.to_string()).collect();
+    writeln!(file, "# shape = {}", shape.join(" "))?;
+    let row_len = array.shape().last().copied().unwrap_or(0);
+    if row_len > 0 {
+        for row in array.data().chunks(row_len) {
+            let row: Vec<String> = row.iter().map(&format).collect();
+            writeln!(file, "{}", row.join(" "))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_array(path: &Path) -> CosmosisResult<Value> {
+    let file = fs::File::open(path).map_err(|e| io_error(path, e))?;
+    let mut ty = None;
+    let mut shape = None;
+    let mut tokens = Vec::new();
+    let mut row_lens = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| io_error(path, e))?;
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('#') {
+            if let Some(eq) = header.find('=') {
+                let value = header[eq + 1..].trim();
+                match header[..eq].trim() {
+                    "type" => ty = Some(parse_type_tag(value).ok_or_else(|| {
+                        parse_error(path, i + 1, format!("unknown type {:?}", value))
+                    })?),
+                    "shape" => shape = Some(value.split_whitespace()
+                                                 .map(|n| n.parse())
+                                                 .collect::<Result<Vec<usize>, _>>()
+                                                 .map_err(|_| parse_error(path, i + 1, format!("bad shape {:?}", value)))?),
+                    _ => ()
+                }
+            }
+            continue;
+        }
+        let before = tokens.len();
+        tokens.extend(line.split_whitespace().map(|t| (i + 1, t.to_string())));
+        if tokens.len() > before {
+            row_lens.push(tokens.len() - before);
+        }
+    }
+
+    // Files written by numpy carry no type header: infer the narrowest type which fits
+    let ty = ty.unwrap_or_else(|| {
+        if tokens.iter().any(|(_, t)| t.ends_with('j') || t.ends_with("j)")) {
+            datablock_type_t::DBT_COMPLEX1D
+        } else if !tokens.is_empty() && tokens.iter().all(|(_, t)| t.parse::<raw::c_int>().is_ok()) {
+            datablock_type_t::DBT_INT1D
+        } else {
+            datablock_type_t::DBT_DOUBLE1D
+        }
+    });
+
+    let bad_token = |line: usize, token: &str| {
+        parse_error(path, line, format!("cannot parse {:?} as {:?}", token, ty))
+    };
+    let ints = || tokens.iter().map(|&(line, ref t)| t.parse().map_err(|_| bad_token(line, t)))
+                        .collect::<CosmosisResult<Vec<raw::c_int>>>();
+    let doubles = || tokens.iter().map(|&(line, ref t)| t.parse().map_err(|_| bad_token(line, t)))
+                           .collect::<CosmosisResult<Vec<f64>>>();
+    let complexes = || tokens.iter().map(|&(line, ref t)| parse_complex(t).ok_or_else(|| bad_token(line, t)))
+                             .collect::<CosmosisResult<Vec<_>>>();
+    match ty {
+        datablock_type_t::DBT_INT1D => ints().map(Value::IntArray),
+        datablock_type_t::DBT_DOUBLE1D => doubles().map(Value::DoubleArray),
+        datablock_type_t::DBT_COMPLEX1D => complexes().map(Value::ComplexArray),
+        datablock_type_t::DBT_INT2D | datablock_type_t::DBT_INTND =>
+            shaped(path, ty, shape, &row_lens, ints()?).map(Value::IntNdArray),
+        datablock_type_t::DBT_DOUBLE2D | datablock_type_t::DBT_DOUBLEND =>
+            shaped(path, ty, shape, &row_lens, doubles()?).map(Value::DoubleNdArray),
+        datablock_type_t::DBT_COMPLEX2D | datablock_type_t::DBT_COMPLEXND =>
+            shaped(path, ty, shape, &row_lens, complexes()?).map(Value::ComplexNdArray),
+        other => Err(parse_error(path, 1, format!("{:?} is not an array type", other)))
+    }
+}
+
+/// Gives the elements of a multi-dimensional array file their shape. Matrices without a
+/// `# shape = ...` header have one row per line, as `numpy.savetxt` writes them.
+fn shaped<T>(path: &Path, ty: datablock_type_t, shape: Option<Vec<usize>>, row_lens: &[usize], data: Vec<T>)
+             -> CosmosisResult<NdArray<T>> {
+    let is_matrix = ty == datablock_type_t::DBT_INT2D || ty == datablock_type_t::DBT_DOUBLE2D ||
+                    ty == datablock_type_t::DBT_COMPLEX2D;
+    let shape = match shape {
+        Some(shape) => shape,
+        None if is_matrix => vec![row_lens.len(), row_lens.first().copied().unwrap_or(0)],
+        None => return Err(parse_error(path, 1, format!("no shape header for {:?}", ty)))
+    };
+    NdArray::new(shape, data).map_err(|e| parse_error(path, 1, e.reason.unwrap_or_default()))
+}
+
+fn read_scalars(path: &Path, db: &mut DataBlock, section: &str) -> CosmosisResult<()> {
+    let file = fs::File::open(path).map_err(|e| io_error(path, e))?;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| io_error(path, e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let eq = line.find('=').ok_or_else(|| {
+            parse_error(path, i + 1, format!("expected `name = value`, found {:?}", line))
+        })?;
+        db.put_value(section, line[..eq].trim(), &parse_scalar(&line[eq + 1..]))?;
+    }
+    Ok(())
+}
+
+impl DataBlock {
+    /// Writes this block in the layout used by the CosmoSIS test sampler: one directory per
+    /// section under `path`, holding a `values.txt` with one `name = value` line per scalar (as
+    /// Python literals) and one `<name>.txt` file per array. Array files start with `# name = ...`
+    /// and `# type = ...` header lines. A 1D array then has one element per line; an array of two
+    /// or more dimensions has a `# shape = ...` header and one row of its last dimension per line.
+    /// Either way the file loads directly with `numpy.loadtxt`.
+    ///
+    /// Fails with `DBS_WRONG_VALUE_TYPE` if the block contains an entry with no `Value`
+    /// representation, and with `DBS_LOGIC_ERROR` on I/O errors, if an array is named `values`, or
+    /// if a section or array name contains `/`, `\` or `..`.
+    pub fn save_to_directory(&self, path: impl AsRef<Path>) -> CosmosisResult<()> {
+        let path = path.as_ref();
+        for section in self.sections() {
+            check_path_component(&section)?;
+            let section_dir = path.join(&section);
+            fs::create_dir_all(&section_dir).map_err(|e| io_error(&section_dir, e))?;
+
+            let mut scalars = Vec::new();
+            for name in self.names(&section)? {
+                let value = self.get_value(&section, &name)?;
+                match format_scalar(&value) {
+                    Some(literal) => scalars.push(format!("{} = {}", name, literal)),
+                    None => {
+                        check_path_component(&name)?;
+                        let array_path = section_dir.join(format!("{}.txt", name));
+                        if array_path.ends_with(SCALAR_FILE) {
+                            return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                                       .with_reason(format!("Array {}/{} would overwrite {}",
+                                                            section, name, SCALAR_FILE)));
+                        }
+                        write_array(&array_path, &name, &value).map_err(|e| io_error(&array_path, e))?;
+                    }
+                }
+            }
+
+            if !scalars.is_empty() {
+                let scalar_path = section_dir.join(SCALAR_FILE);
+                let mut contents = scalars.join("\n");
+                contents.push('\n');
+                fs::write(&scalar_path, contents).map_err(|e| io_error(&scalar_path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a block written by `save_to_directory` (or by CosmoSIS itself). Array files without
+    /// a `# type = ...` header are read as complex if any element looks complex, as integers if
+    /// every element is an integer, and as doubles otherwise.
+    pub fn load_from_directory(path: impl AsRef<Path>) -> CosmosisResult<Self> {
+        let path = path.as_ref();
+        let mut db = DataBlock::new();
+
+        let mut section_dirs = fs::read_dir(path).map_err(|e| io_error(path, e))?
+                                                 .map(|entry| entry.map(|e| e.path()))
+                                                 .collect::<io::Result<Vec<_>>>()
+                                                 .map_err(|e| io_error(path, e))?;
+        section_dirs.retain(|p| p.is_dir());
+        section_dirs.sort();
+
+        for section_dir in section_dirs {
+            let section = match section_dir.file_name().and_then(|s| s.to_str()) {
+                Some(section) => section.to_string(),
+                None => continue
+            };
+
+            let mut files = fs::read_dir(&section_dir).map_err(|e| io_error(&section_dir, e))?
+                                                      .map(|entry| entry.map(|e| e.path()))
+                                                      .collect::<io::Result<Vec<_>>>()
+                                                      .map_err(|e| io_error(&section_dir, e))?;
+            files.retain(|p| p.is_file() && p.extension().map(|ext| ext == "txt").unwrap_or(false));
+            files.sort();
+
+            for file in files {
+                if file.ends_with(SCALAR_FILE) {
+                    read_scalars(&file, &mut db, &section)?;
+                } else if let Some(name) = file.file_stem().and_then(|s| s.to_str()) {
+                    db.put_value(&section, name, &read_array(&file)?)?;
+                }
+            }
+        }
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_complex, parse_scalar, Complex, DataBlock, DATABLOCK_STATUS, NdArray, Value};
+    use std::env;
+    use std::fs;
+    use std::os::raw;
+
+    #[test]
+    fn test_parse_scalar() {
+        assert_eq!(parse_scalar("3"), Value::Int(3));
+        assert_eq!(parse_scalar("3.0"), Value::Double(3.0));
+        assert_eq!(parse_scalar("1e-07"), Value::Double(1e-7));
+        assert_eq!(parse_scalar("True"), Value::Bool(true));
+        assert_eq!(parse_scalar("'it\\'s'"), Value::String("it's".to_string()));
+        assert_eq!(parse_scalar("(1-2.5e-3j)"), Value::Complex(Complex { re: 1.0, im: -2.5e-3 }));
+        assert_eq!(parse_complex("(-1e+2+3j)"), Some(Complex { re: -100.0, im: 3.0 }));
+        assert!(match parse_scalar("nan") { Value::Double(x) => x.is_nan(), _ => false });
+    }
+
+    #[test]
+    fn test_directory_round_trip() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).unwrap();
+        db.put("cosmological_parameters", "flat", true).unwrap();
+        db.put::<str, _>("cosmological_parameters", "label", "Planck 'TT'\n").unwrap();
+        db.put("cosmological_parameters", "z_c", Complex { re: 1.0, im: -0.5 }).unwrap();
+        db.put::<[f64], _>("distances", "z", &[0.0, 0.1, 1e-300, 2.5][..]).unwrap();
+        db.put::<[raw::c_int], _>("distances", "bins", &[1, 2, 3][..]).unwrap();
+        db.put::<[Complex<f64>], _>("distances", "phase", &[Complex { re: 0.0, im: 1.0 }][..]).unwrap();
+        db.put::<[f64], _>("distances", "empty", &[][..]).unwrap();
+        db.put("distances", "cov", NdArray::new(vec![2, 3], vec![1.0, 0.5, 0.0, 0.5, 2.0, 1e-300]).unwrap()).unwrap();
+        db.put("distances", "cube", NdArray::new(vec![2, 1, 2], vec![1, 2, 3, 4]).unwrap()).unwrap();
+
+        let dir = env::temp_dir().join(format!("cosmosis-directory-test-{}", ::std::process::id()));
+        db.save_to_directory(&dir).unwrap();
+        assert!(dir.join("distances").join("z.txt").is_file());
+        assert!(dir.join("cosmological_parameters").join("values.txt").is_file());
+
+        let loaded = DataBlock::load_from_directory(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn test_save_rejects_paths() {
+        let dir = env::temp_dir().join(format!("cosmosis-directory-paths-{}", ::std::process::id()));
+        let mut db = DataBlock::new();
+        db.put::<[f64], _>("distances", "../../escaped", &[1.0][..]).unwrap();
+        assert_eq!(db.save_to_directory(dir.join("out")).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(!dir.join("escaped.txt").exists());
+
+        let mut db = DataBlock::new();
+        db.put("a\\b", "x", 1.0).unwrap();
+        assert_eq!(db.save_to_directory(&dir).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_numpy_array() {
+        let dir = env::temp_dir().join(format!("cosmosis-directory-numpy-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("matter_power_lin")).unwrap();
+        fs::write(dir.join("matter_power_lin").join("k_h.txt"),
+                  "# k_h\n1.000000000000000000e-04\n1.000000000000000000e+00\n").unwrap();
+
+        let loaded = DataBlock::load_from_directory(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.get::<Vec<f64>>("matter_power_lin", "k_h").unwrap(), vec![1e-4, 1.0]);
+    }
+}
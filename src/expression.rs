@@ -0,0 +1,515 @@
+use std::error;
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::{CosmosisError, DATABLOCK_STATUS};
+
+/// A malformed expression, or one which could not be evaluated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpressionError {
+    pub expression: String,
+    /// The byte offset in `expression` at which parsing failed, if it did.
+    pub position: Option<usize>,
+    pub message: String
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in expression {:?}", self.message, self.expression)?;
+        if let Some(position) = self.position {
+            write!(f, " at column {}", position + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ExpressionError {}
+
+impl From<ExpressionError> for CosmosisError {
+    fn from(err: ExpressionError) -> Self {
+        CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(err.to_string())
+    }
+}
+
+/// A reference to a parameter in an expression: `section--name`, or just `name` for one in
+/// the same section as whatever the expression defines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    pub section: Option<String>,
+    pub name: String
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.section {
+            Some(ref section) => write!(f, "{}--{}", section, self.name),
+            None => write!(f, "{}", self.name)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(f64),
+    Variable(Reference),
+    Unary(fn(f64) -> f64, Box<Node>),
+    Binary(fn(f64, f64) -> f64, Box<Node>, Box<Node>)
+}
+
+impl Node {
+    fn evaluate(&self, lookup: &dyn Fn(&Reference) -> Option<f64>) -> Result<f64, String> {
+        Ok(match *self {
+            Node::Number(x) => x,
+            Node::Variable(ref reference) => lookup(reference).ok_or_else(|| format!("Unknown parameter {}", reference))?,
+            Node::Unary(f, ref x) => f(x.evaluate(lookup)?),
+            Node::Binary(f, ref x, ref y) => f(x.evaluate(lookup)?, y.evaluate(lookup)?)
+        })
+    }
+
+    fn references<'a>(&'a self, references: &mut Vec<&'a Reference>) {
+        match *self {
+            Node::Number(_) => (),
+            Node::Variable(ref reference) => references.push(reference),
+            Node::Unary(_, ref x) => x.references(references),
+            Node::Binary(_, ref x, ref y) => {
+                x.references(references);
+                y.references(references);
+            }
+        }
+    }
+}
+
+/// A small arithmetic expression over parameters, e.g. `cosmological_parameters--omega_m - ombh2 / h0^2`.
+///
+/// Expressions may use numbers, parameter references (see `Reference`), `+ - * /`, `^` (or `**`)
+/// for powers, parentheses, the constant `pi`, and the functions `sqrt`, `exp`, `log` (natural),
+/// `log10`, `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `abs`, `pow`, `atan2`, `min` and `max`.
+/// Since `--` joins a section to a name, subtracting a negated value needs a space: `a - -b`.
+///
+/// Comparisons (`< <= > >= == !=`) and logic (`and`/`&&`, `or`/`||`, `not`/`!`) bind more
+/// loosely than arithmetic, and give 1 for true and 0 for false. Any non-zero value is true.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    source: String,
+    root: Node
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { source, tokens: &tokens, next: 0 };
+        let root = parser.expression()?;
+        match parser.peek() {
+            Some(&(position, ref token)) => Err(parser.error(position, format!("Unexpected {}", token))),
+            None => Ok(Expression { source: source.to_string(), root })
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Every parameter the expression refers to, in order of appearance.
+    pub fn references(&self) -> Vec<&Reference> {
+        let mut references = Vec::new();
+        self.root.references(&mut references);
+        references
+    }
+
+    /// Evaluates the expression, looking up each parameter it refers to with `lookup`.
+    pub fn evaluate(&self, lookup: &dyn Fn(&Reference) -> Option<f64>) -> Result<f64, ExpressionError> {
+        self.root.evaluate(lookup).map_err(|message| {
+            ExpressionError { expression: self.source.clone(), position: None, message }
+        })
+    }
+}
+
+/// Expressions are equal if they were written identically.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(&'static str),
+    Open,
+    Close,
+    Comma
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Number(x) => write!(f, "number {}", x),
+            Token::Identifier(ref name) => write!(f, "name `{}`", name),
+            Token::Operator(op) => write!(f, "`{}`", op),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`")
+        }
+    }
+}
+
+fn truth(condition: bool) -> f64 {
+    if condition { 1.0 } else { 0.0 }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let error = |position: usize, message: String| {
+        ExpressionError { expression: source.to_string(), position: Some(position), message }
+    };
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < source.len() {
+        let c = source[i..].chars().next().unwrap();
+        let start = i;
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        let token = if c.is_ascii_digit() || c == '.' {
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            Token::Number(source[start..i].parse().map_err(|_| error(start, format!("Invalid number {:?}", &source[start..i])))?)
+        } else if is_identifier_start(c) {
+            let identifier_end = |mut i: usize| {
+                while i < bytes.len() && is_identifier_char(bytes[i] as char) {
+                    i += 1;
+                }
+                i
+            };
+            i = identifier_end(i);
+            if source[i..].starts_with("--") && source[i + 2..].starts_with(is_identifier_start) {
+                i = identifier_end(i + 2);
+            }
+            Token::Identifier(source[start..i].to_string())
+        } else {
+            i += 1;
+            match c {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                '+' => Token::Operator("+"),
+                '-' => Token::Operator("-"),
+                '/' => Token::Operator("/"),
+                '^' => Token::Operator("^"),
+                '*' if source[i..].starts_with('*') => {
+                    i += 1;
+                    Token::Operator("^")
+                },
+                '*' => Token::Operator("*"),
+                '<' | '>' | '=' | '!' => {
+                    let op = match (c, source[i..].starts_with('=')) {
+                        ('<', true) => "<=",
+                        ('>', true) => ">=",
+                        ('=', true) => "==",
+                        ('!', true) => "!=",
+                        ('<', false) => "<",
+                        ('>', false) => ">",
+                        ('!', false) => "!",
+                        _ => return Err(error(start, "Expected `==`".to_string()))
+                    };
+                    i += op.len() - 1;
+                    Token::Operator(op)
+                },
+                '&' | '|' => {
+                    let op = if c == '&' { "&&" } else { "||" };
+                    if !source[i..].starts_with(c) {
+                        return Err(error(start, format!("Expected `{}`", op)));
+                    }
+                    i += 1;
+                    Token::Operator(op)
+                },
+                _ => return Err(error(start, format!("Unexpected character {:?}", c)))
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [(usize, Token)],
+    next: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, position: usize, message: String) -> ExpressionError {
+        ExpressionError { expression: self.source.to_string(), position: Some(position), message }
+    }
+
+    fn peek(&self) -> Option<&'a (usize, Token)> {
+        self.tokens.get(self.next)
+    }
+
+    /// Consumes the next token if it is `token`.
+    fn accept(&mut self, token: &Token) -> bool {
+        match self.peek() {
+            Some((_, next)) if next == token => {
+                self.next += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), ExpressionError> {
+        if self.accept(token) {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(&(position, ref found)) => self.error(position, format!("Expected {}, found {}", token, found)),
+            None => self.error(self.source.len(), format!("Expected {}, found the end", token))
+        })
+    }
+
+    /// Accepts either spelling of a logical operator, e.g. `and` or `&&`.
+    fn accept_logical(&mut self, word: &str, symbol: &'static str) -> bool {
+        self.accept(&Token::Identifier(word.to_string())) || self.accept(&Token::Operator(symbol))
+    }
+
+    /// `expression := conjunction (("or" | "||") conjunction)*`
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.conjunction()?;
+        while self.accept_logical("or", "||") {
+            node = Node::Binary(|x, y| truth(x != 0.0 || y != 0.0), Box::new(node), Box::new(self.conjunction()?));
+        }
+        Ok(node)
+    }
+
+    /// `conjunction := negation (("and" | "&&") negation)*`
+    fn conjunction(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.negation()?;
+        while self.accept_logical("and", "&&") {
+            node = Node::Binary(|x, y| truth(x != 0.0 && y != 0.0), Box::new(node), Box::new(self.negation()?));
+        }
+        Ok(node)
+    }
+
+    /// `negation := ("not" | "!") negation | comparison`
+    fn negation(&mut self) -> Result<Node, ExpressionError> {
+        if self.accept_logical("not", "!") {
+            return Ok(Node::Unary(|x| truth(x == 0.0), Box::new(self.negation()?)));
+        }
+        self.comparison()
+    }
+
+    /// `comparison := sum (("<" | "<=" | ">" | ">=" | "==" | "!=") sum)?`
+    fn comparison(&mut self) -> Result<Node, ExpressionError> {
+        let node = self.sum()?;
+        let op: fn(f64, f64) -> f64 = match self.peek() {
+            Some(&(_, Token::Operator("<"))) => |x, y| truth(x < y),
+            Some(&(_, Token::Operator("<="))) => |x, y| truth(x <= y),
+            Some(&(_, Token::Operator(">"))) => |x, y| truth(x > y),
+            Some(&(_, Token::Operator(">="))) => |x, y| truth(x >= y),
+            Some(&(_, Token::Operator("=="))) => |x, y| truth(x == y),
+            Some(&(_, Token::Operator("!="))) => |x, y| truth(x != y),
+            _ => return Ok(node)
+        };
+        self.next += 1;
+        Ok(Node::Binary(op, Box::new(node), Box::new(self.sum()?)))
+    }
+
+    /// `sum := term (("+" | "-") term)*`
+    fn sum(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.term()?;
+        loop {
+            let op: fn(f64, f64) -> f64 = if self.accept(&Token::Operator("+")) {
+                |x, y| x + y
+            } else if self.accept(&Token::Operator("-")) {
+                |x, y| x - y
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    /// `term := unary (("*" | "/") unary)*`
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+        loop {
+            let op: fn(f64, f64) -> f64 = if self.accept(&Token::Operator("*")) {
+                |x, y| x * y
+            } else if self.accept(&Token::Operator("/")) {
+                |x, y| x / y
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    /// `unary := ("-" | "+") unary | primary ("^" unary)?`, so that `-x^2` is `-(x^2)`.
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        if self.accept(&Token::Operator("-")) {
+            return Ok(Node::Unary(|x| -x, Box::new(self.unary()?)));
+        }
+        if self.accept(&Token::Operator("+")) {
+            return self.unary();
+        }
+        let base = self.primary()?;
+        if self.accept(&Token::Operator("^")) {
+            return Ok(Node::Binary(f64::powf, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    /// `primary := number | name | name "(" arguments ")" | "(" expression ")"`
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        let &(position, ref token) = match self.peek() {
+            Some(next) => next,
+            None => return Err(self.error(self.source.len(), "Unexpected end of expression".to_string()))
+        };
+        self.next += 1;
+        match *token {
+            Token::Number(x) => Ok(Node::Number(x)),
+            Token::Open => {
+                let node = self.expression()?;
+                self.expect(&Token::Close)?;
+                Ok(node)
+            },
+            Token::Identifier(ref name) if self.accept(&Token::Open) => self.call(position, name),
+            Token::Identifier(ref name) if name == "pi" => Ok(Node::Number(PI)),
+            Token::Identifier(ref name) => {
+                let reference = match name.find("--") {
+                    Some(i) => Reference { section: Some(name[..i].to_string()), name: name[i + 2..].to_string() },
+                    None => Reference { section: None, name: name.to_string() }
+                };
+                Ok(Node::Variable(reference))
+            },
+            _ => Err(self.error(position, format!("Unexpected {}", token)))
+        }
+    }
+
+    fn call(&mut self, position: usize, function: &str) -> Result<Node, ExpressionError> {
+        let mut arguments = Vec::new();
+        if !self.accept(&Token::Close) {
+            loop {
+                arguments.push(self.expression()?);
+                if self.accept(&Token::Close) {
+                    break;
+                }
+                self.expect(&Token::Comma)?;
+            }
+        }
+
+        let unary: Option<fn(f64) -> f64> = match function {
+            "sqrt" => Some(f64::sqrt),
+            "exp" => Some(f64::exp),
+            "log" => Some(f64::ln),
+            "log10" => Some(f64::log10),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "tan" => Some(f64::tan),
+            "asin" => Some(f64::asin),
+            "acos" => Some(f64::acos),
+            "atan" => Some(f64::atan),
+            "abs" => Some(f64::abs),
+            _ => None
+        };
+        let binary: Option<fn(f64, f64) -> f64> = match function {
+            "pow" => Some(f64::powf),
+            "atan2" => Some(f64::atan2),
+            "min" => Some(f64::min),
+            "max" => Some(f64::max),
+            _ => None
+        };
+        let arity = if unary.is_some() { 1 } else if binary.is_some() { 2 } else {
+            return Err(self.error(position, format!("Unknown function `{}`", function)));
+        };
+        if arguments.len() != arity {
+            return Err(self.error(position, format!("`{}` takes {} argument(s), found {}", function, arity, arguments.len())));
+        }
+
+        let mut arguments = arguments.into_iter().map(Box::new);
+        let x = arguments.next().unwrap();
+        Ok(match (unary, binary) {
+            (Some(f), _) => Node::Unary(f, x),
+            (_, Some(f)) => Node::Binary(f, x, arguments.next().unwrap()),
+            _ => unreachable!()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expression, Reference};
+
+    fn evaluate(source: &str) -> f64 {
+        let lookup = |reference: &Reference| match (reference.section.as_deref(), reference.name.as_str()) {
+            (Some("cosmological_parameters"), "omega_m") => Some(0.3),
+            (None, "h0") => Some(0.7),
+            _ => None
+        };
+        Expression::parse(source).unwrap().evaluate(&lookup).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9.0);
+        assert_eq!(evaluate("-2^2"), -4.0);
+        assert_eq!(evaluate("2**3**2"), 512.0);
+        assert_eq!(evaluate("2^-1"), 0.5);
+        assert_eq!(evaluate("10 - 4 - 3"), 3.0);
+        assert_eq!(evaluate("1.5e1 / 3"), 5.0);
+        assert_eq!(evaluate("max(1, sqrt(16)) + abs(-1)"), 5.0);
+        assert_eq!(evaluate("cos(pi)"), -1.0);
+        assert!((evaluate("cosmological_parameters--omega_m * h0^2") - 0.147).abs() < 1e-12);
+        assert!((evaluate("h0 - -cosmological_parameters--omega_m") - 1.0).abs() < 1e-12);
+        assert_eq!(evaluate("1 + 1 == 2"), 1.0);
+        assert_eq!(evaluate("h0 > 0.5 and not h0 >= 1 || 0"), 1.0);
+        assert_eq!(evaluate("!(1 < 2) or 3 != 3"), 0.0);
+        assert_eq!(evaluate("2 <= 1 && 1"), 0.0);
+
+        let expression = Expression::parse("a--b + c * a--b").unwrap();
+        assert_eq!(expression.references().iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+                   vec!["a--b", "c", "a--b"]);
+        let err = expression.evaluate(&|_: &Reference| None).unwrap_err();
+        assert!(err.to_string().contains("Unknown parameter a--b"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for &(source, position) in &[("1 +", Some(3)), ("(1", Some(2)), ("1 2", Some(2)), ("foo(1)", Some(0)),
+                                      ("max(1)", Some(0)), ("1 $ 2", Some(2)), ("", Some(0)), ("1,", Some(1)),
+                                      ("1 = 2", Some(2)), ("1 & 2", Some(2)), ("1 < 2 < 3", Some(6))] {
+            let err = Expression::parse(source).unwrap_err();
+            assert_eq!(err.position, position, "{}", err);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, Value};
+
+/// One derived quantity to save with each sample: `section/name` for a scalar, or
+/// `section/name#n` for an array of `n` elements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtraOutput {
+    pub section: String,
+    pub name: String,
+    /// The element count of an array output; `None` for a scalar.
+    pub count: Option<usize>
+}
+
+impl ExtraOutput {
+    /// Column names as CosmoSIS writes them: `section--name`, or `section--name_0` to
+    /// `section--name_{n-1}` for arrays.
+    pub fn column_names(&self) -> Vec<String> {
+        match self.count {
+            Some(count) => (0..count).map(|i| format!("{}--{}_{}", self.section, self.name, i)).collect(),
+            None => vec![format!("{}--{}", self.section, self.name)]
+        }
+    }
+
+    fn error(&self, kind: DATABLOCK_STATUS, message: impl fmt::Display) -> CosmosisError {
+        CosmosisError::new(kind).with_reason(format!("Could not extract extra output {}: {}", self, message))
+    }
+
+    /// Reads the output's values from `db`. Integers are converted to doubles, and an array must
+    /// have exactly the declared number of elements.
+    pub fn extract(&self, db: &DataBlock) -> CosmosisResult<Vec<f64>> {
+        let value = db.get_value(self.section.as_str(), self.name.as_str())
+                      .map_err(|err| self.error(err.kind, err))?;
+        let values = match (self.count, value) {
+            (None, Value::Double(x)) => vec![x],
+            (None, Value::Int(n)) => vec![f64::from(n)],
+            (Some(_), Value::DoubleArray(v)) => v,
+            (Some(_), Value::IntArray(v)) => v.into_iter().map(f64::from).collect(),
+            (_, other) => {
+                let expected = if self.count.is_some() { "a real or integer array" } else { "a real or integer scalar" };
+                return Err(self.error(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE,
+                                      format!("expected {}, found {}", expected, other)));
+            }
+        };
+        match self.count {
+            Some(count) if count != values.len() => {
+                Err(self.error(DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH,
+                               format!("expected {} elements, found {}", count, values.len())))
+            },
+            _ => Ok(values)
+        }
+    }
+}
+
+impl FromStr for ExtraOutput {
+    type Err = CosmosisError;
+
+    fn from_str(spec: &str) -> CosmosisResult<Self> {
+        let error = |message: &str| {
+            CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("Invalid extra_output {:?}: {}", spec, message))
+        };
+        let (path, count) = match spec.find('#') {
+            Some(i) => {
+                let count = spec[i + 1..].parse::<usize>().ok().filter(|&count| count > 0)
+                                         .ok_or_else(|| error("the element count must be a positive integer"))?;
+                (&spec[..i], Some(count))
+            },
+            None => (spec, None)
+        };
+        let mut parts = path.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(section), Some(name)) if !section.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(ExtraOutput { section: section.to_string(), name: name.to_string(), count })
+            },
+            _ => Err(error("expected section/name or section/name#count"))
+        }
+    }
+}
+
+impl fmt::Display for ExtraOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.section, self.name)?;
+        if let Some(count) = self.count {
+            write!(f, "#{}", count)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `extra_output` option of a pipeline: derived quantities saved alongside the sampled
+/// parameters, flattened into a single row of doubles.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtraOutputs {
+    outputs: Vec<ExtraOutput>
+}
+
+impl ExtraOutputs {
+    /// Parses a list of specs separated by whitespace, e.g.
+    /// `cosmological_parameters/sigma_8 data_vector/theory#20`.
+    pub fn parse(option: &str) -> CosmosisResult<Self> {
+        let outputs = option.split_whitespace().map(str::parse).collect::<CosmosisResult<Vec<_>>>()?;
+        Ok(ExtraOutputs { outputs })
+    }
+
+    pub fn outputs(&self) -> &[ExtraOutput] {
+        &self.outputs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// The number of columns in a row.
+    pub fn len(&self) -> usize {
+        self.outputs.iter().map(|output| output.count.unwrap_or(1)).sum()
+    }
+
+    /// The name of each column in a row.
+    pub fn column_names(&self) -> Vec<String> {
+        self.outputs.iter().flat_map(ExtraOutput::column_names).collect()
+    }
+
+    /// Reads every output from `db` into one row, in the order given by `column_names`.
+    pub fn extract(&self, db: &DataBlock) -> CosmosisResult<Vec<f64>> {
+        let mut row = Vec::with_capacity(self.len());
+        for output in &self.outputs {
+            row.extend(output.extract(db)?);
+        }
+        Ok(row)
+    }
+
+    /// A row of NaNs, for samples at which the pipeline did not run.
+    pub fn missing_row(&self) -> Vec<f64> {
+        vec![f64::NAN; self.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtraOutput, ExtraOutputs};
+    use std::os::raw;
+    use {DataBlock, DATABLOCK_STATUS};
+
+    #[test]
+    fn test_parse_specs() {
+        let outputs = ExtraOutputs::parse("cosmological_parameters/sigma_8  data_vector/theory#3\n").unwrap();
+        assert_eq!(outputs.outputs()[1],
+                   ExtraOutput { section: "data_vector".to_string(), name: "theory".to_string(), count: Some(3) });
+        assert_eq!(outputs.outputs()[1].to_string(), "data_vector/theory#3");
+        assert_eq!(outputs.len(), 4);
+        assert_eq!(outputs.column_names(), vec!["cosmological_parameters--sigma_8", "data_vector--theory_0",
+                                                "data_vector--theory_1", "data_vector--theory_2"]);
+        assert!(ExtraOutputs::parse("").unwrap().is_empty());
+
+        for spec in &["sigma_8", "/sigma_8", "a/", "a/b/c", "a/b#", "a/b#0", "a/b#-1", "a/b#x"] {
+            assert!(spec.parse::<ExtraOutput>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "sigma_8", 0.8).unwrap();
+        db.put("data_vector", "nbin", 3 as raw::c_int).unwrap();
+        db.put::<[f64], _>("data_vector", "theory", &[1.0, 2.0, 3.0][..]).unwrap();
+
+        let outputs = ExtraOutputs::parse("cosmological_parameters/sigma_8 data_vector/nbin data_vector/theory#3").unwrap();
+        assert_eq!(outputs.extract(&db).unwrap(), vec![0.8, 3.0, 1.0, 2.0, 3.0]);
+        assert_eq!(outputs.missing_row().len(), 5);
+
+        let err = ExtraOutputs::parse("data_vector/theory#4").unwrap().extract(&db).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH);
+        let err = ExtraOutputs::parse("data_vector/theory").unwrap().extract(&db).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+        let err = ExtraOutputs::parse("data_vector/missing").unwrap().extract(&db).unwrap_err();
+        assert!(err.to_string().contains("data_vector/missing"), "{}", err);
+    }
+}
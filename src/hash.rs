@@ -0,0 +1,205 @@
+use super::{Complex, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, Value};
+use value::type_tag;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// The bit pattern every NaN is hashed as.
+const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+/// 64-bit FNV-1a over an explicit little-endian encoding. Unlike `std::hash`, the result is
+/// fixed by this file alone, not by the compiler version or a per-process seed.
+struct ContentHasher {
+    state: u64
+}
+
+impl ContentHasher {
+    fn new() -> Self {
+        ContentHasher { state: FNV_OFFSET_BASIS }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.bytes(&n.to_le_bytes());
+    }
+
+    /// Length-prefixed, so that `("ab", "c")` and `("a", "bc")` hash differently.
+    fn str(&mut self, s: &str) {
+        self.u64(s.len() as u64);
+        self.bytes(s.as_bytes());
+    }
+
+    fn f64(&mut self, x: f64) {
+        if x.is_nan() {
+            self.u64(CANONICAL_NAN);
+        } else if x == 0.0 {
+            self.u64(0);
+        } else {
+            self.u64(x.to_bits());
+        }
+    }
+
+    fn complex(&mut self, z: &Complex<f64>) {
+        self.f64(z.re);
+        self.f64(z.im);
+    }
+
+    fn value(&mut self, value: &Value) {
+        self.str(&type_tag(value.datablock_type()));
+        if let Some(shape) = value.shape() {
+            self.u64(shape.len() as u64);
+            shape.iter().for_each(|&n| self.u64(n as u64));
+        }
+        match *value {
+            Value::Int(n) => self.bytes(&n.to_le_bytes()),
+            Value::Bool(b) => self.bytes(&[b as u8]),
+            Value::Double(x) => self.f64(x),
+            Value::Complex(ref z) => self.complex(z),
+            Value::String(ref s) => self.str(s),
+            Value::IntArray(ref v) => v.iter().for_each(|n| self.bytes(&n.to_le_bytes())),
+            Value::DoubleArray(ref v) => v.iter().for_each(|&x| self.f64(x)),
+            Value::ComplexArray(ref v) => v.iter().for_each(|z| self.complex(z)),
+            Value::IntNdArray(ref a) => a.data().iter().for_each(|n| self.bytes(&n.to_le_bytes())),
+            Value::DoubleNdArray(ref a) => a.data().iter().for_each(|&x| self.f64(x)),
+            Value::ComplexNdArray(ref a) => a.data().iter().for_each(|z| self.complex(z)),
+        }
+    }
+
+    fn entry(&mut self, db: &DataBlock, section: &str, name: &str) -> CosmosisResult<()> {
+        self.str(section);
+        self.str(name);
+        self.value(&db.get_value(section, name)?);
+        Ok(())
+    }
+
+    fn section(&mut self, db: &DataBlock, section: &str) -> CosmosisResult<()> {
+        let mut names = db.names(section)?;
+        names.sort();
+        self.u64(names.len() as u64);
+        for name in &names {
+            self.entry(db, section, name)?;
+        }
+        Ok(())
+    }
+}
+
+impl DataBlock {
+    /// A hash of the whole block.
+    ///
+    /// Content hashes depend only on the sections, names, types and values in a block, so they are
+    /// independent of insertion order, and are stable across process runs, platforms and compiler
+    /// versions, making them suitable as keys for an on-disk cache. Metadata is not hashed.
+    ///
+    /// Doubles are hashed by bit pattern, except that every NaN hashes as the same canonical NaN
+    /// regardless of sign or payload, and `-0.0` hashes as `0.0`. Values which compare equal
+    /// therefore hash equally, and a NaN input always maps to the same cache entry. The type is part
+    /// of the hash, so an int `1` and a double `1.0` hash differently.
+    ///
+    /// Blocks containing types without a `Value` representation cannot be hashed.
+    pub fn content_hash(&self) -> CosmosisResult<u64> {
+        let mut sections = self.sections();
+        sections.sort();
+        let mut hasher = ContentHasher::new();
+        hasher.u64(sections.len() as u64);
+        for section in &sections {
+            hasher.str(section);
+            hasher.section(self, section)?;
+        }
+        Ok(hasher.state)
+    }
+
+    /// A hash of one section, including its name. Fails with `DBS_SECTION_NOT_FOUND` if there is
+    /// no such section.
+    pub fn section_hash(&self, section: &str) -> CosmosisResult<u64> {
+        let mut hasher = ContentHasher::new();
+        hasher.str(section);
+        hasher.section(self, section)?;
+        Ok(hasher.state)
+    }
+
+    /// A hash of the given `(section, name)` entries, in any order. Fails if an entry is missing,
+    /// so that a cache is never keyed on an incomplete set of inputs.
+    pub fn entries_hash(&self, entries: &[(&str, &str)]) -> CosmosisResult<u64> {
+        let mut entries = entries.to_vec();
+        entries.sort();
+        entries.dedup();
+        let mut hasher = ContentHasher::new();
+        hasher.u64(entries.len() as u64);
+        for &(section, name) in &entries {
+            if !self.contains(section, name) {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND)
+                           .with_reason(format!("Cannot hash missing value at (section, name): ({}, {})",
+                                                section, name)));
+            }
+            hasher.entry(self, section, name)?;
+        }
+        Ok(hasher.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::os::raw;
+    use {DataBlock, DATABLOCK_STATUS, NdArray};
+
+    #[test]
+    fn test_hash_order_independent() {
+        let mut a = DataBlock::new();
+        a.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        a.put("cosmological_parameters", "h0", 0.7).unwrap();
+        a.put::<[f64], _>("distances", "z", &[0.0, 1.0][..]).unwrap();
+
+        let mut b = DataBlock::new();
+        b.put::<[f64], _>("distances", "z", &[0.0, 1.0][..]).unwrap();
+        b.put("cosmological_parameters", "h0", 0.7).unwrap();
+        b.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+
+        assert_eq!(a.content_hash().unwrap(), b.content_hash().unwrap());
+        assert_eq!(a.section_hash("distances").unwrap(), b.section_hash("distances").unwrap());
+        assert_eq!(a.entries_hash(&[("distances", "z"), ("cosmological_parameters", "h0")]).unwrap(),
+                   b.entries_hash(&[("cosmological_parameters", "h0"), ("distances", "z")]).unwrap());
+        // Pinned so that an accidental change to the encoding, which would invalidate on-disk
+        // caches, fails loudly.
+        assert_eq!(a.section_hash("distances").unwrap(), 0x538a_61a2_e196_6134);
+
+        b.insert("cosmological_parameters", "omega_m", 0.31).unwrap();
+        assert_ne!(a.content_hash().unwrap(), b.content_hash().unwrap());
+        assert_eq!(a.section_hash("distances").unwrap(), b.section_hash("distances").unwrap());
+        assert_eq!(a.section_hash("nonexistent").unwrap_err().kind, DATABLOCK_STATUS::DBS_SECTION_NOT_FOUND);
+        assert!(a.entries_hash(&[("distances", "w")]).is_err());
+    }
+
+    #[test]
+    fn test_hash_float_policy() {
+        let hash_of = |x: f64| {
+            let mut db = DataBlock::new();
+            db.put("s", "x", x).unwrap();
+            db.content_hash().unwrap()
+        };
+        assert_eq!(hash_of(0.0), hash_of(-0.0));
+        assert_eq!(hash_of(f64::NAN), hash_of(-f64::NAN));
+        assert_eq!(hash_of(f64::NAN), hash_of(f64::from_bits(0x7ff0_0000_0000_0001)));
+        assert_ne!(hash_of(1.0), hash_of(1.0 + f64::EPSILON));
+
+        let mut int = DataBlock::new();
+        int.put("s", "x", 1 as raw::c_int).unwrap();
+        assert_ne!(int.content_hash().unwrap(), hash_of(1.0));
+    }
+
+    #[test]
+    fn test_hash_shape_rank() {
+        let hash_of = |shape: Vec<usize>, data: Vec<f64>| {
+            let mut db = DataBlock::new();
+            db.put("s", "x", NdArray::new(shape, data).unwrap()).unwrap();
+            db.content_hash().unwrap()
+        };
+        // Without the rank, both would hash the words 1, 1, 1, 0.
+        assert_ne!(hash_of(vec![1, 1, 1], vec![0.0]), hash_of(vec![1, 1, 1, 0], vec![]));
+    }
+}
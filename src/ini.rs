@@ -0,0 +1,531 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+
+/// The section whose entries are visible from every other section, as in Python's ConfigParser.
+pub const DEFAULT_SECTION: &str = "DEFAULT";
+
+/// How many `%(name)s` references may be followed from a single value. Deeper nesting almost
+/// always means a cycle.
+const MAX_INTERPOLATION_DEPTH: usize = 10;
+
+/// Where an entry was defined: a line of the ini file, or of a file it `%include`s.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IniLocation {
+    /// `None` when parsed from a string rather than loaded from a file.
+    pub file: Option<PathBuf>,
+    /// Counted from 1.
+    pub line: usize
+}
+
+impl fmt::Display for IniLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}", file.display(), self.line),
+            None => write!(f, "line {}", self.line)
+        }
+    }
+}
+
+/// A malformed ini file, or one which could not be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IniError {
+    /// The offending line, if the error is in the file's contents.
+    pub location: Option<IniLocation>,
+    pub message: String
+}
+
+impl IniError {
+    fn at(location: &IniLocation, message: impl Into<String>) -> Self {
+        IniError { location: Some(location.clone()), message: message.into() }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.location.as_ref().map(|location| location.line)
+    }
+}
+
+impl fmt::Display for IniError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(f, "{}: {}", location, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl error::Error for IniError {}
+
+impl From<IniError> for CosmosisError {
+    fn from(err: IniError) -> Self {
+        CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(format!("Invalid ini file: {}", err))
+    }
+}
+
+/// One `key = value` line, after interpolation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IniEntry {
+    /// Lower-cased, as CosmoSIS does.
+    pub key: String,
+    pub value: String,
+    /// `None` for entries added with `Ini::set`.
+    pub location: Option<IniLocation>
+}
+
+/// The entries under one `[section]` header, in the order they were first defined. Sections
+/// which appear more than once, or in several files, are merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IniSection {
+    name: String,
+    entries: Vec<IniEntry>
+}
+
+impl IniSection {
+    fn new(name: &str) -> Self {
+        IniSection { name: name.to_string(), entries: Vec::new() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// This section's own entries, not including those from `DEFAULT_SECTION`.
+    pub fn entries(&self) -> &[IniEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, key: &str) -> Option<&IniEntry> {
+        let key = key.to_lowercase();
+        self.entries.iter().find(|entry| entry.key == key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entry(key).map(|entry| entry.value.as_str())
+    }
+
+    /// Adds `entry`, replacing any earlier entry with the same key in place.
+    fn set(&mut self, entry: IniEntry) {
+        match self.entries.iter_mut().find(|existing| existing.key == entry.key) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry)
+        }
+    }
+}
+
+/// A parsed CosmoSIS ini file, such as a pipeline's `params.ini` or its `values.ini`.
+///
+/// The syntax is that of Python's ConfigParser, which CosmoSIS uses:
+///
+/// * `[section]` headers, followed by `key = value` (or `key: value`) lines. Keys are
+///   case-insensitive; section names are not.
+/// * Comments on lines starting with `;` or `#`, or after a `;` or `#` preceded by whitespace.
+/// * Continuation lines: an indented line directly after an entry extends its value, joined
+///   with a newline.
+/// * `%(key)s` is replaced by another key's value from the same section or `[DEFAULT]`, and
+///   `%%` by `%`.
+/// * `${NAME}` is replaced by the environment variable `NAME`, and left alone if it is unset.
+/// * `%include path` reads another ini file at that point, relative to the including file.
+///   Later definitions override earlier ones, in either file.
+///
+/// ```
+/// # extern crate cosmosis;
+/// # use cosmosis::Ini;
+/// # fn main() {
+/// let ini = Ini::parse("[DEFAULT]\n\
+///                       root = /data\n\
+///                       [camb]\n\
+///                       file = %(root)s/camb.so  ; the module\n").unwrap();
+/// assert_eq!(ini.get("camb", "file"), Some("/data/camb.so"));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ini {
+    path: Option<PathBuf>,
+    defaults: IniSection,
+    sections: Vec<IniSection>
+}
+
+impl Default for Ini {
+    fn default() -> Self {
+        Ini::new()
+    }
+}
+
+impl Ini {
+    /// An empty configuration.
+    pub fn new() -> Self {
+        Ini { path: None, defaults: IniSection::new(DEFAULT_SECTION), sections: Vec::new() }
+    }
+
+    /// Parses an ini file's contents. `%include` paths are relative to the working directory.
+    pub fn parse(text: &str) -> Result<Self, IniError> {
+        let mut ini = Ini::new();
+        ini.parse_text(text, None, &mut Vec::new())?;
+        ini.interpolate()?;
+        Ok(ini)
+    }
+
+    /// Reads and parses the ini file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IniError> {
+        let path = path.as_ref();
+        let mut ini = Ini::new();
+        ini.path = Some(path.to_path_buf());
+        ini.parse_file(path, None, &mut Vec::new())?;
+        ini.interpolate()?;
+        Ok(ini)
+    }
+
+    /// The file this was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The directory containing the file this was loaded from, against which relative paths in
+    /// its options should be resolved; see `Options::relative_to`.
+    pub fn directory(&self) -> Option<&Path> {
+        self.path().map(|path| path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Every section except `DEFAULT_SECTION`, in the order they first appear.
+    pub fn sections(&self) -> &[IniSection] {
+        &self.sections
+    }
+
+    pub fn section(&self, name: &str) -> Option<&IniSection> {
+        if name == DEFAULT_SECTION {
+            Some(&self.defaults)
+        } else {
+            self.sections.iter().find(|section| section.name == name)
+        }
+    }
+
+    pub fn defaults(&self) -> &IniSection {
+        &self.defaults
+    }
+
+    pub fn has_section(&self, name: &str) -> bool {
+        self.section(name).is_some()
+    }
+
+    /// The entry for `key` in `section`, falling back on `DEFAULT_SECTION`.
+    pub fn entry(&self, section: &str, key: &str) -> Option<&IniEntry> {
+        self.section(section).and_then(|section| section.entry(key))
+            .or_else(|| self.section(section).and(self.defaults.entry(key)))
+    }
+
+    /// The value of `key` in `section`, falling back on `DEFAULT_SECTION`.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entry(section, key).map(|entry| entry.value.as_str())
+    }
+
+    /// Every entry visible from `section`: its own, then any from `DEFAULT_SECTION` which it does
+    /// not override. Empty if there is no such section.
+    pub fn items(&self, section: &str) -> Vec<&IniEntry> {
+        let own = match self.section(section) {
+            Some(own) => own,
+            None => return Vec::new()
+        };
+        let mut items: Vec<_> = own.entries.iter().collect();
+        if section != DEFAULT_SECTION {
+            items.extend(self.defaults.entries.iter().filter(|entry| own.entry(&entry.key).is_none()));
+        }
+        items
+    }
+
+    /// Sets `key` in `section`, creating the section if needed, e.g. to apply a command-line
+    /// override. `value` is used as given, without interpolation.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        self.section_mut(section).set(IniEntry { key: key.to_lowercase(), value: value.to_string(), location: None });
+    }
+
+    /// A new `DataBlock` holding every section, as strings; see `load_into`.
+    pub fn to_datablock(&self) -> CosmosisResult<DataBlock> {
+        let mut db = DataBlock::new();
+        self.load_into(&mut db)?;
+        Ok(db)
+    }
+
+    /// Stores each entry of each section (including those inherited from `DEFAULT_SECTION`) as a
+    /// string in the section of the same name, replacing any existing string values.
+    pub fn load_into(&self, db: &mut DataBlock) -> CosmosisResult<()> {
+        for section in &self.sections {
+            for entry in self.items(&section.name) {
+                db.put_or_overwrite::<str, _>(section.name.as_str(), entry.key.as_str(), entry.value.as_str())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn section_mut(&mut self, name: &str) -> &mut IniSection {
+        if name == DEFAULT_SECTION {
+            return &mut self.defaults;
+        }
+        match self.sections.iter().position(|section| section.name == name) {
+            Some(i) => &mut self.sections[i],
+            None => {
+                self.sections.push(IniSection::new(name));
+                self.sections.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Parses the file at `path`, which was `%include`d from `from` if given. `stack` holds the
+    /// files currently being parsed, to catch circular includes.
+    fn parse_file(&mut self, path: &Path, from: Option<&IniLocation>, stack: &mut Vec<PathBuf>) -> Result<(), IniError> {
+        let error = |message: String| IniError { location: from.cloned(), message };
+        let canonical = fs::canonicalize(path)
+            .map_err(|err| error(format!("Could not read {}: {}", path.display(), err)))?;
+        if stack.contains(&canonical) {
+            return Err(error(format!("Circular %include of {}", path.display())));
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| error(format!("Could not read {}: {}", path.display(), err)))?;
+        stack.push(canonical);
+        let result = self.parse_text(&text, Some(path), stack);
+        stack.pop();
+        result
+    }
+
+    fn parse_text(&mut self, text: &str, file: Option<&Path>, stack: &mut Vec<PathBuf>) -> Result<(), IniError> {
+        let mut current: Option<String> = None;
+        // The key whose value an indented line would continue.
+        let mut last_key: Option<String> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let location = IniLocation { file: file.map(Path::to_path_buf), line: i + 1 };
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                last_key = None;
+                continue;
+            }
+            if trimmed.starts_with(';') || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with(char::is_whitespace) {
+                if let (Some(section), Some(key)) = (current.as_ref(), last_key.as_ref()) {
+                    let entries = &mut self.section_mut(section).entries;
+                    let entry = entries.iter_mut().find(|entry| &entry.key == key).expect("continued entry exists");
+                    entry.value.push('\n');
+                    entry.value.push_str(strip_comment(trimmed));
+                    continue;
+                }
+            }
+
+            if let Some(target) = trimmed.strip_prefix("%include") {
+                let target = expand_env(strip_comment(target).trim());
+                if target.is_empty() {
+                    return Err(IniError::at(&location, "%include needs a file name"));
+                }
+                let target = match file.and_then(Path::parent) {
+                    Some(dir) => dir.join(target),
+                    None => PathBuf::from(target)
+                };
+                self.parse_file(&target, Some(&location), stack)?;
+                last_key = None;
+                continue;
+            }
+
+            if trimmed.starts_with('[') {
+                let header = strip_comment(trimmed);
+                if !header.ends_with(']') {
+                    return Err(IniError::at(&location, format!("Unterminated section header: {}", header)));
+                }
+                let name = header[1..header.len() - 1].trim();
+                if name.is_empty() {
+                    return Err(IniError::at(&location, "Empty section name"));
+                }
+                current = Some(name.to_string());
+                last_key = None;
+                continue;
+            }
+
+            let section = current.as_ref().ok_or_else(|| {
+                IniError::at(&location, format!("Entry before the first [section] header: {}", trimmed))
+            })?;
+            let delimiter = trimmed.find(['=', ':']).ok_or_else(|| {
+                IniError::at(&location, format!("Expected `key = value`, found: {}", trimmed))
+            })?;
+            let key = trimmed[..delimiter].trim().to_lowercase();
+            if key.is_empty() {
+                return Err(IniError::at(&location, format!("Missing key before `{}`", &trimmed[delimiter..delimiter + 1])));
+            }
+            let value = strip_comment(trimmed[delimiter + 1..].trim()).to_string();
+            self.section_mut(section).set(IniEntry { key: key.clone(), value, location: Some(location) });
+            last_key = Some(key);
+        }
+        Ok(())
+    }
+
+    /// Expands `%(key)s` references and then environment variables in every entry. Run once all
+    /// files are read, so that references may point forwards or into other files.
+    fn interpolate(&mut self) -> Result<(), IniError> {
+        let defaults = self.interpolate_section(&self.defaults)?;
+        let sections = self.sections.iter().map(|section| self.interpolate_section(section))
+                                           .collect::<Result<Vec<_>, _>>()?;
+        self.defaults = defaults;
+        self.sections = sections;
+        Ok(())
+    }
+
+    fn interpolate_section(&self, section: &IniSection) -> Result<IniSection, IniError> {
+        let mut interpolated = IniSection::new(&section.name);
+        for entry in &section.entries {
+            let value = match entry.location {
+                Some(ref location) => expand_env(&self.interpolate_value(section, &entry.value, location, 0)?),
+                None => entry.value.clone()
+            };
+            interpolated.entries.push(IniEntry { value, ..entry.clone() });
+        }
+        Ok(interpolated)
+    }
+
+    fn interpolate_value(&self, section: &IniSection, value: &str, location: &IniLocation, depth: usize)
+        -> Result<String, IniError> {
+        if depth > MAX_INTERPOLATION_DEPTH {
+            return Err(IniError::at(location, "%(...)s references nested too deeply; is there a cycle?"));
+        }
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("%%") {
+                expanded.push('%');
+                rest = after;
+            } else if rest.starts_with("%(") {
+                let end = rest.find(")s").ok_or_else(|| {
+                    IniError::at(location, format!("Unterminated %(...)s reference in: {}", value))
+                })?;
+                let key = rest[2..end].trim();
+                let referenced = section.get(key).or_else(|| self.defaults.get(key)).ok_or_else(|| {
+                    IniError::at(location, format!("%({})s refers to a key missing from [{}]", key, section.name))
+                })?;
+                expanded.push_str(&self.interpolate_value(section, referenced, location, depth + 1)?);
+                rest = &rest[end + 2..];
+            } else {
+                expanded.push('%');
+                rest = &rest[1..];
+            }
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+}
+
+/// Removes a trailing `; comment` or `# comment`. The comment character must follow whitespace,
+/// so that values like `file#1` are left alone.
+fn strip_comment(text: &str) -> &str {
+    let mut previous_space = false;
+    for (i, c) in text.char_indices() {
+        if previous_space && (c == ';' || c == '#') {
+            return text[..i].trim_end();
+        }
+        previous_space = c.is_whitespace();
+    }
+    text
+}
+
+/// Replaces each `${NAME}` with the value of the environment variable `NAME`. Unset variables
+/// are left as written, as CosmoSIS does.
+fn expand_env(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break
+        };
+        expanded.push_str(&rest[..start]);
+        match env::var(&rest[start + 2..end]) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => expanded.push_str(&rest[start..=end])
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ini, DEFAULT_SECTION};
+    use std::{env, fs};
+
+    #[test]
+    fn test_parse() {
+        env::set_var("COSMOSIS_INI_TEST_ROOT", "/opt/csl");
+        let ini = Ini::parse("; a pipeline\n\
+                              [DEFAULT]\n\
+                              root = ${COSMOSIS_INI_TEST_ROOT}\n\
+                              \n\
+                              [pipeline]\n\
+                              Modules = consistency camb\n\
+                              \x20   halofit  # continued\n\
+                              values: values.ini\n\
+                              \n\
+                              [camb]\n\
+                              file = %(root)s/boltzmann/camb.so ; comment\n\
+                              label = 100%% ${COSMOSIS_INI_TEST_UNSET}#1\n").unwrap();
+
+        assert_eq!(ini.sections().iter().map(|section| section.name()).collect::<Vec<_>>(), vec!["pipeline", "camb"]);
+        assert_eq!(ini.get("pipeline", "modules"), Some("consistency camb\nhalofit"));
+        assert_eq!(ini.get("pipeline", "VALUES"), Some("values.ini"));
+        assert_eq!(ini.get("camb", "file"), Some("/opt/csl/boltzmann/camb.so"));
+        assert_eq!(ini.get("camb", "label"), Some("100% ${COSMOSIS_INI_TEST_UNSET}#1"));
+        assert_eq!(ini.get("camb", "root"), Some("/opt/csl"));
+        assert_eq!(ini.get("nonexistent", "root"), None);
+        assert_eq!(ini.entry("camb", "file").unwrap().location.as_ref().unwrap().line, 11);
+        assert_eq!(ini.items("camb").len(), 3);
+        assert_eq!(ini.defaults().name(), DEFAULT_SECTION);
+
+        let db = ini.to_datablock().unwrap();
+        assert_eq!(db.get::<String>("camb", "file").unwrap(), "/opt/csl/boltzmann/camb.so");
+        assert_eq!(db.get::<String>("pipeline", "root").unwrap(), "/opt/csl");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [("x = 1\n", 1),
+                     ("[a]\n\n[b\n", 3),
+                     ("[a]\nno delimiter\n", 2),
+                     ("[a]\nx = 1\n\n = 2\n", 4),
+                     ("[a]\nx = %(y)s\n", 2),
+                     ("[a]\nx = %(y)s\ny = %(x)s\n", 2),
+                     ("[a]\n\n%include /nonexistent/cosmosis.ini\n", 3)];
+        for &(text, line) in &cases {
+            let err = Ini::parse(text).unwrap_err();
+            assert_eq!(err.line(), Some(line), "{:?}: {}", text, err);
+            assert!(err.to_string().starts_with(&format!("line {}: ", line)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = env::temp_dir().join(format!("cosmosis-ini-test-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("params.ini"), "[runtime]\nsampler = test\n\
+                                           %include sub/common.ini\n\
+                                           [camb]\nzmax = 3.0\n").unwrap();
+        fs::write(dir.join("sub/common.ini"), "[camb]\nzmax = 2.0\nfeedback = 0\n").unwrap();
+        fs::write(dir.join("loop.ini"), "%include loop.ini\n").unwrap();
+
+        let mut ini = Ini::load(dir.join("params.ini")).unwrap();
+        assert_eq!(ini.directory(), Some(dir.as_path()));
+        assert_eq!(ini.get("camb", "zmax"), Some("3.0"));
+        assert_eq!(ini.get("camb", "feedback"), Some("0"));
+        let location = ini.entry("camb", "feedback").unwrap().location.clone().unwrap();
+        assert_eq!((location.file.unwrap(), location.line), (dir.join("sub/common.ini"), 3));
+
+        ini.set("camb", "feedback", "%(zmax)s");
+        assert_eq!(ini.get("camb", "feedback"), Some("%(zmax)s"));
+
+        let err = Ini::load(dir.join("loop.ini")).unwrap_err();
+        assert!(err.message.contains("Circular"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,372 @@
+use std::io::{Read, Write};
+use std::os::raw;
+
+use serde_json::{self, Map, Value as Json};
+
+use super::{Complex, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, DEFAULT_METADATA_KEYS, Key, Value};
+use ndarray::NdArray;
+use value::{parse_type_tag, type_tag};
+
+/// Identifies documents written by `JsonWriter`.
+const FORMAT_NAME: &str = "cosmosis-datablock";
+/// Bumped whenever the document layout changes incompatibly.
+const FORMAT_VERSION: u64 = 1;
+
+fn json_error(err: serde_json::Error) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(format!("JSON error: {}", err))
+}
+
+fn entry_error(section: &str, name: &str, message: &str) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+        .with_reason(format!("Invalid JSON entry at (section, name): ({}, {}): {}", section, name, message))
+}
+
+/// JSON has no representation for non-finite numbers, so they are written as strings.
+fn float_to_json(x: f64) -> Json {
+    if x.is_finite() {
+        Json::from(x)
+    } else if x.is_nan() {
+        Json::from("NaN")
+    } else if x > 0.0 {
+        Json::from("Infinity")
+    } else {
+        Json::from("-Infinity")
+    }
+}
+
+fn float_from_json(json: &Json) -> Option<f64> {
+    match *json {
+        Json::Number(ref n) => n.as_f64(),
+        Json::String(ref s) => match s.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn int_from_json(json: &Json) -> Option<raw::c_int> {
+    json.as_i64().and_then(|n| if n as raw::c_int as i64 == n { Some(n as raw::c_int) } else { None })
+}
+
+fn complex_to_json(z: &Complex<f64>) -> Json {
+    Json::Array(vec![float_to_json(z.re), float_to_json(z.im)])
+}
+
+fn complex_from_json(json: &Json) -> Option<Complex<f64>> {
+    match json.as_array() {
+        Some(parts) if parts.len() == 2 => Some(Complex { re: float_from_json(&parts[0])?,
+                                                          im: float_from_json(&parts[1])? }),
+        _ => None
+    }
+}
+
+fn array_from_json<T, F>(json: &Json, element: F) -> Option<Vec<T>>
+    where F: Fn(&Json) -> Option<T> {
+    json.as_array().and_then(|elements| elements.iter().map(element).collect())
+}
+
+/// Multi-dimensional arrays are stored flat, in row-major order, so need their shape.
+fn ndarray_from_json<T>(section: &str, name: &str, shape: &Option<Vec<usize>>, data: Option<Vec<T>>)
+                        -> CosmosisResult<Option<NdArray<T>>> {
+    match (data, shape.clone()) {
+        (None, _) => Ok(None),
+        (Some(_), None) => Err(entry_error(section, name, "missing \"shape\"")),
+        (Some(data), Some(shape)) => NdArray::new(shape, data)
+            .map(Some)
+            .map_err(|e| entry_error(section, name, &e.reason.unwrap_or_default()))
+    }
+}
+
+fn entry_to_json(value: &Value, metadata: Vec<(String, String)>) -> Json {
+    let mut entry = Map::new();
+    entry.insert("type".to_string(), Json::from(type_tag(value.datablock_type())));
+    if let Some(shape) = value.shape() {
+        entry.insert("shape".to_string(), Json::Array(shape.into_iter().map(Json::from).collect()));
+    }
+    let json = match *value {
+        Value::Int(n) => Json::from(n),
+        Value::Bool(b) => Json::from(b),
+        Value::Double(x) => float_to_json(x),
+        Value::Complex(ref z) => complex_to_json(z),
+        Value::String(ref s) => Json::from(s.as_str()),
+        Value::IntArray(ref v) => Json::Array(v.iter().map(|&n| Json::from(n)).collect()),
+        Value::DoubleArray(ref v) => Json::Array(v.iter().map(|&x| float_to_json(x)).collect()),
+        Value::ComplexArray(ref v) => Json::Array(v.iter().map(complex_to_json).collect()),
+        Value::IntNdArray(ref a) => Json::Array(a.data().iter().map(|&n| Json::from(n)).collect()),
+        Value::DoubleNdArray(ref a) => Json::Array(a.data().iter().map(|&x| float_to_json(x)).collect()),
+        Value::ComplexNdArray(ref a) => Json::Array(a.data().iter().map(complex_to_json).collect()),
+    };
+    entry.insert("value".to_string(), json);
+    if !metadata.is_empty() {
+        entry.insert("metadata".to_string(),
+                     Json::Object(metadata.into_iter().map(|(k, v)| (k, Json::from(v))).collect()));
+    }
+    Json::Object(entry)
+}
+
+fn entry_from_json(section: &str, name: &str, json: &Json) -> CosmosisResult<(Value, Vec<(String, String)>)> {
+    let entry = json.as_object().ok_or_else(|| entry_error(section, name, "not an object"))?;
+    let tag = entry.get("type").and_then(|t| t.as_str())
+                   .ok_or_else(|| entry_error(section, name, "missing \"type\""))?;
+    let ty = parse_type_tag(tag).ok_or_else(|| entry_error(section, name, &format!("unsupported type {:?}", tag)))?;
+    let json = entry.get("value").ok_or_else(|| entry_error(section, name, "missing \"value\""))?;
+    let shape = match entry.get("shape") {
+        None => None,
+        Some(shape) => Some(shape.as_array()
+                                 .and_then(|extents| extents.iter().map(|n| n.as_u64().map(|n| n as usize)).collect())
+                                 .ok_or_else(|| entry_error(section, name, &format!("invalid shape {}", shape)))?)
+    };
+
+    let value = match tag {
+        "DBT_INT" => int_from_json(json).map(Value::Int),
+        "DBT_BOOL" => json.as_bool().map(Value::Bool),
+        "DBT_DOUBLE" => float_from_json(json).map(Value::Double),
+        "DBT_COMPLEX" => complex_from_json(json).map(Value::Complex),
+        "DBT_STRING" => json.as_str().map(|s| Value::String(s.to_string())),
+        "DBT_INT1D" => array_from_json(json, int_from_json).map(Value::IntArray),
+        "DBT_DOUBLE1D" => array_from_json(json, float_from_json).map(Value::DoubleArray),
+        "DBT_COMPLEX1D" => array_from_json(json, complex_from_json).map(Value::ComplexArray),
+        "DBT_INT2D" | "DBT_INTND" =>
+            ndarray_from_json(section, name, &shape, array_from_json(json, int_from_json))?.map(Value::IntNdArray),
+        "DBT_DOUBLE2D" | "DBT_DOUBLEND" =>
+            ndarray_from_json(section, name, &shape, array_from_json(json, float_from_json))?.map(Value::DoubleNdArray),
+        "DBT_COMPLEX2D" | "DBT_COMPLEXND" =>
+            ndarray_from_json(section, name, &shape, array_from_json(json, complex_from_json))?.map(Value::ComplexNdArray),
+        _ => None
+    }.ok_or_else(|| entry_error(section, name, &format!("value does not match type {:?}", ty)))?;
+
+    if shape.is_some() && shape != value.shape() {
+        return Err(entry_error(section, name, &format!("shape {:?} does not match value", shape.unwrap_or_default())));
+    }
+
+    let metadata = match entry.get("metadata") {
+        None => Vec::new(),
+        Some(metadata) => {
+            let metadata = metadata.as_object()
+                                   .ok_or_else(|| entry_error(section, name, "\"metadata\" is not an object"))?;
+            metadata.iter()
+                    .map(|(k, v)| v.as_str()
+                                   .map(|v| (k.clone(), v.to_string()))
+                                   .ok_or_else(|| entry_error(section, name, "metadata values must be strings")))
+                    .collect::<CosmosisResult<_>>()?
+        }
+    };
+    Ok((value, metadata))
+}
+
+/// Writes `DataBlock`s as JSON one entry at a time, without building the whole document in
+/// memory. The document has the form
+///
+/// ```text
+/// {"format":"cosmosis-datablock","version":1,"sections":{
+/// "distances":{
+/// "cov":{"shape":[2,2],"type":"DBT_DOUBLE2D","value":[1.0,0.5,0.5,2.0]},
+/// "z":{"shape":[3],"type":"DBT_DOUBLE1D","value":[0.0,0.5,1.0]}
+/// }}}
+/// ```
+///
+/// with sections and names sorted and one entry per line, so that documents diff cleanly. Every
+/// entry carries its `datablock_type_t` tag, so integers, doubles and complex numbers survive a
+/// round trip. Arrays carry their `shape`, and multi-dimensional ones are flattened in row-major
+/// order. Complex numbers are `[re, im]` pairs, and non-finite doubles are the strings
+/// `"NaN"`, `"Infinity"` and `"-Infinity"`.
+///
+/// Metadata is only exported under the keys given to `metadata_keys`, `DEFAULT_METADATA_KEYS` by
+/// default: the CosmoSIS C API can look metadata up by key but not list it, so metadata under any
+/// other key is silently dropped. Modules which attach their own keys should pass them here for a
+/// document to hold everything in the block.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    metadata_keys: Vec<String>
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// A writer which exports metadata under `DEFAULT_METADATA_KEYS`.
+    pub fn new(writer: W) -> Self {
+        JsonWriter {
+            writer,
+            metadata_keys: DEFAULT_METADATA_KEYS.iter().map(|k| k.to_string()).collect()
+        }
+    }
+
+    /// Sets the metadata keys looked up for every entry, replacing the defaults.
+    pub fn metadata_keys<K: AsRef<str>>(mut self, keys: &[K]) -> Self {
+        self.metadata_keys = keys.iter().map(|k| k.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Writes `db` as one complete JSON document. Fails with `DBS_WRONG_VALUE_TYPE` if `db`
+    /// contains an entry with no `Value` representation.
+    pub fn write(&mut self, db: &DataBlock) -> CosmosisResult<()> {
+        write!(self.writer, "{{\"format\":\"{}\",\"version\":{},\"sections\":{{", FORMAT_NAME, FORMAT_VERSION)?;
+
+        let mut sections = db.sections();
+        sections.sort();
+        for (i, section) in sections.iter().enumerate() {
+            if i > 0 {
+                write!(self.writer, ",")?;
+            }
+            writeln!(self.writer)?;
+            serde_json::to_writer(&mut self.writer, section).map_err(json_error)?;
+            write!(self.writer, ":{{")?;
+
+            let mut names = db.names(section)?;
+            names.sort();
+            for (j, name) in names.iter().enumerate() {
+                let value = db.get_value(section, name)?;
+                let metadata = db.metadata_for(section, name, &self.metadata_keys);
+                if j > 0 {
+                    write!(self.writer, ",")?;
+                }
+                writeln!(self.writer)?;
+                serde_json::to_writer(&mut self.writer, name).map_err(json_error)?;
+                write!(self.writer, ":")?;
+                serde_json::to_writer(&mut self.writer, &entry_to_json(&value, metadata)).map_err(json_error)?;
+            }
+            write!(self.writer, "\n}}")?;
+        }
+        writeln!(self.writer, "}}}}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl DataBlock {
+    /// Serializes this block to a JSON document; see `JsonWriter` for the format. Only metadata
+    /// under `DEFAULT_METADATA_KEYS` is included; use `JsonWriter::metadata_keys` for others.
+    pub fn to_json(&self) -> CosmosisResult<String> {
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.write(self)?;
+        Ok(String::from_utf8(writer.into_inner()).expect("serde_json writes valid UTF-8"))
+    }
+
+    /// Streams this block as JSON to `writer`; see `JsonWriter` for the format. Only metadata
+    /// under `DEFAULT_METADATA_KEYS` is included, as for `to_json`.
+    pub fn write_json(&self, writer: impl Write) -> CosmosisResult<()> {
+        JsonWriter::new(writer).write(self)
+    }
+
+    /// Reads a block from a JSON document written by `to_json` or `JsonWriter`.
+    pub fn from_json(json: &str) -> CosmosisResult<Self> {
+        DataBlock::from_json_value(&serde_json::from_str(json).map_err(json_error)?)
+    }
+
+    /// Reads a block from a JSON document written by `to_json` or `JsonWriter`.
+    pub fn read_json(reader: impl Read) -> CosmosisResult<Self> {
+        DataBlock::from_json_value(&serde_json::from_reader(reader).map_err(json_error)?)
+    }
+
+    fn from_json_value(json: &Json) -> CosmosisResult<Self> {
+        let invalid = |message: &str| {
+            CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("Invalid DataBlock JSON: {}", message))
+        };
+        let document = json.as_object().ok_or_else(|| invalid("not an object"))?;
+        if document.get("format").and_then(|f| f.as_str()) != Some(FORMAT_NAME) {
+            return Err(invalid("missing \"format\": \"cosmosis-datablock\""));
+        }
+        match document.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version <= FORMAT_VERSION => (),
+            _ => return Err(invalid(&format!("unsupported version (expected at most {})", FORMAT_VERSION)))
+        }
+        let sections = document.get("sections").and_then(|s| s.as_object())
+                               .ok_or_else(|| invalid("missing \"sections\" object"))?;
+
+        let mut db = DataBlock::new();
+        for (section, entries) in sections {
+            let entries = entries.as_object()
+                                 .ok_or_else(|| invalid(&format!("section {} is not an object", section)))?;
+            // Keys come from an untrusted document, so are checked for NUL bytes rather than
+            // left to panic.
+            let section_key = Key::new(section)?;
+            for (name, entry) in entries {
+                let (value, metadata) = entry_from_json(section, name, entry)?;
+                let name_key = Key::new(name)?;
+                db.put_value(&section_key, &name_key, &value)?;
+                for (key, data) in metadata {
+                    db.put_metadata(&section_key, &name_key, Key::new(&key)?, &data)?;
+                }
+            }
+        }
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonWriter;
+    use std::f64;
+    use std::os::raw;
+    use {Complex, DataBlock, DATABLOCK_STATUS, NdArray};
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).unwrap();
+        db.put("cosmological_parameters", "whole", 3.0).unwrap();
+        db.put("cosmological_parameters", "flat", true).unwrap();
+        db.put("cosmological_parameters", "z_c", Complex { re: 1.0, im: -0.5 }).unwrap();
+        db.put::<str, _>("cosmological_parameters", "label", "Planck \"TT\"").unwrap();
+        db.put::<[f64], _>("distances", "d_a", &[0.1, f64::INFINITY, 1e-300][..]).unwrap();
+        db.put::<[raw::c_int], _>("distances", "bins", &[1, 2][..]).unwrap();
+        db.put::<[Complex<f64>], _>("distances", "phase", &[Complex { re: 0.0, im: 1.0 }][..]).unwrap();
+        db.put("distances", "cov", NdArray::new(vec![2, 2], vec![1, 0, 0, 1]).unwrap()).unwrap();
+        db.put_metadata("distances", "d_a", "unit", "Mpc").unwrap();
+
+        let json = db.to_json().unwrap();
+        assert!(json.contains("\n\"whole\":{\"type\":\"DBT_DOUBLE\",\"value\":3.0}"));
+        assert!(json.contains("\"metadata\":{\"unit\":\"Mpc\"}"));
+        assert!(json.contains("\n\"cov\":{\"shape\":[2,2],\"type\":\"DBT_INT2D\",\"value\":[1,0,0,1]}"), "{}", json);
+
+        let loaded = DataBlock::from_json(&json).unwrap();
+        assert_eq!(db, loaded);
+        assert_eq!(loaded.get::<raw::c_int>("cosmological_parameters", "n_massive").unwrap(), 3);
+        assert_eq!(loaded.get_metadata("distances", "d_a", "unit").unwrap(), "Mpc");
+
+        db.put_metadata("distances", "d_a", "source", "camb").unwrap();
+        assert!(!db.to_json().unwrap().contains("camb"));
+        let keys = vec!["unit".to_string(), "source".to_string()];
+        let mut writer = JsonWriter::new(Vec::new()).metadata_keys(&keys);
+        writer.write(&db).unwrap();
+        let json = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(DataBlock::from_json(&json).unwrap().get_metadata("distances", "d_a", "source").unwrap(), "camb");
+
+        let mut streamed = Vec::new();
+        JsonWriter::new(&mut streamed).metadata_keys::<&str>(&[]).write(&db).unwrap();
+        let streamed = String::from_utf8(streamed).unwrap();
+        assert!(!streamed.contains("metadata"));
+        assert_eq!(DataBlock::read_json(streamed.as_bytes()).unwrap(), db);
+    }
+
+    #[test]
+    fn test_json_errors() {
+        let bad_type = r#"{"format":"cosmosis-datablock","version":1,"sections":{
+                          "s":{"x":{"type":"DBT_INT","value":1.5}}}}"#;
+        assert_eq!(DataBlock::from_json(bad_type).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+
+        let bad_shape = r#"{"format":"cosmosis-datablock","version":1,"sections":{
+                           "s":{"x":{"type":"DBT_DOUBLE1D","shape":[3],"value":[1.0]}}}}"#;
+        assert!(DataBlock::from_json(bad_shape).is_err());
+        let no_shape = r#"{"format":"cosmosis-datablock","version":1,"sections":{
+                          "s":{"x":{"type":"DBT_DOUBLE2D","value":[1.0]}}}}"#;
+        assert!(DataBlock::from_json(no_shape).is_err());
+        let nul = r#"{"format":"cosmosis-datablock","version":1,"sections":{
+                     "s":{"x":{"type":"DBT_STRING","value":"a\u0000b"}}}}"#;
+        assert_eq!(DataBlock::from_json(nul).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        let nul_metadata = r#"{"format":"cosmosis-datablock","version":1,"sections":{
+                              "s":{"x":{"type":"DBT_INT","value":1,"metadata":{"unit":"\u0000"}}}}}"#;
+        assert_eq!(DataBlock::from_json(nul_metadata).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        let nul_name = r#"{"format":"cosmosis-datablock","version":1,"sections":{"s":{"x\u0000":{"type":"DBT_INT","value":1}}}}"#;
+        assert_eq!(DataBlock::from_json(nul_name).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(DataBlock::from_json("{\"sections\":{}}").is_err());
+        assert!(DataBlock::from_json("not json").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::borrow::Cow;
+use std::ffi::{CStr, CString};
+use std::fmt;
+
+use super::{CosmosisError, CosmosisResult, DATABLOCK_STATUS};
+
+/// Longest `str` key (in bytes, not counting the NUL terminator) which is
+/// terminated in a stack buffer rather than a freshly allocated `CString`.
+const STACK_KEY_LEN: usize = 127;
+
+/// Types which may name a section or a value in a `DataBlock`.
+///
+/// Implemented for `str`, `String`, `CStr`, `CString` and `Key`, and for
+/// references to any of those. `CStr`s and `Key`s are handed to CosmoSIS
+/// as-is; short `str`s are NUL-terminated on the stack, so no variant
+/// allocates on the common path.
+pub trait AsKey {
+    /// Calls `f` with a NUL-terminated view of this key.
+    ///
+    /// Panics if the key contains an interior NUL byte; use `Key::new` to
+    /// validate untrusted names up front, or `try_with_cstr`.
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R;
+
+    /// As `with_cstr`, but fails with `DBS_LOGIC_ERROR` instead of panicking
+    /// if the key contains an interior NUL byte.
+    fn try_with_cstr<R, F>(&self, f: F) -> CosmosisResult<R>
+        where F: FnOnce(&CStr) -> R {
+        Ok(self.with_cstr(f))
+    }
+}
+
+/// Calls `f` with NUL-terminated views of both `section` and `name`, failing
+/// with `DBS_LOGIC_ERROR` if either contains a NUL byte.
+pub(crate) fn with_keys<S, N, R, F>(section: &S, name: &N, f: F) -> CosmosisResult<R>
+    where S: AsKey + ?Sized,
+          N: AsKey + ?Sized,
+          F: FnOnce(&CStr, &CStr) -> CosmosisResult<R> {
+    section.try_with_cstr(|section| name.try_with_cstr(|name| f(section, name)))??
+}
+
+fn nul_error(name: &str) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+        .with_reason(format!("Key contains a NUL byte: {:?}", name))
+}
+
+impl AsKey for str {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        self.try_with_cstr(f).expect("DataBlock keys must not contain NUL bytes")
+    }
+
+    fn try_with_cstr<R, F>(&self, f: F) -> CosmosisResult<R>
+        where F: FnOnce(&CStr) -> R {
+        let bytes = self.as_bytes();
+        if bytes.contains(&0) {
+            return Err(nul_error(self));
+        }
+        if bytes.len() <= STACK_KEY_LEN {
+            let mut buf = [0u8; STACK_KEY_LEN + 1];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Ok(f(CStr::from_bytes_with_nul(&buf[..bytes.len() + 1]).unwrap()))
+        } else {
+            Ok(f(&CString::new(self).unwrap()))
+        }
+    }
+}
+
+impl AsKey for String {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        self.as_str().with_cstr(f)
+    }
+
+    fn try_with_cstr<R, F>(&self, f: F) -> CosmosisResult<R>
+        where F: FnOnce(&CStr) -> R {
+        self.as_str().try_with_cstr(f)
+    }
+}
+
+impl AsKey for CStr {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        f(self)
+    }
+}
+
+impl AsKey for CString {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        f(self.as_c_str())
+    }
+}
+
+impl<T> AsKey for &T where T: AsKey + ?Sized {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        (**self).with_cstr(f)
+    }
+
+    fn try_with_cstr<R, F>(&self, f: F) -> CosmosisResult<R>
+        where F: FnOnce(&CStr) -> R {
+        (**self).try_with_cstr(f)
+    }
+}
+
+/// A section or value name which has been validated and NUL-terminated once,
+/// so that it can be reused for any number of `DataBlock` accesses without
+/// further conversion.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    inner: Cow<'static, CStr>
+}
+
+/// Section names are ordinary keys; the alias documents intent at call sites.
+pub type SectionName = Key;
+
+impl Key {
+    /// Builds a key from `name`, failing if it contains a NUL byte.
+    pub fn new(name: &str) -> CosmosisResult<Self> {
+        CString::new(name)
+            .map(|cstr| Key { inner: Cow::Owned(cstr) })
+            .map_err(|_| nul_error(name))
+    }
+
+    /// Wraps an already NUL-terminated static string without copying it.
+    pub fn from_static(name: &'static CStr) -> Self {
+        Key { inner: Cow::Borrowed(name) }
+    }
+
+    pub fn as_c_str(&self) -> &CStr {
+        &self.inner
+    }
+}
+
+impl AsKey for Key {
+    fn with_cstr<R, F>(&self, f: F) -> R
+        where F: FnOnce(&CStr) -> R {
+        f(&self.inner)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inner.to_string_lossy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsKey, Key, DATABLOCK_STATUS};
+
+    #[test]
+    fn test_key_validation() {
+        assert_eq!(Key::new("bad\0key").unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!("bad\0key".try_with_cstr(|_| ()).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+
+        let key = Key::new("omega_m").unwrap();
+        assert_eq!(key.as_c_str().to_bytes(), b"omega_m");
+        assert_eq!(key.to_string(), "omega_m");
+    }
+
+    #[test]
+    fn test_long_str_key() {
+        let long = "x".repeat(1000);
+        assert_eq!(long.with_cstr(|cstr| cstr.to_bytes().len()), 1000);
+        assert_eq!("short".with_cstr(|cstr| cstr.to_bytes().len()), 5);
+    }
+}
@@ -1,16 +1,113 @@
+// Lets code generated by `#[derive(CosmosisSection)]`, which refers to `::cosmosis`, compile
+// within this crate too.
+extern crate self as cosmosis;
+extern crate cosmosis_derive;
 extern crate libc;
+extern crate libm;
+extern crate rand;
+extern crate serde_json;
 
 use std::borrow::Borrow;
 use std::convert::From;
 use std::error;
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::io;
 use std::os::raw;
 
+macro_rules! wrap_cosmosis_result {
+    ( $err:expr, $obj:expr ) => {
+        if $err == DATABLOCK_STATUS::DBS_SUCCESS {
+            Ok($obj)
+        } else {
+            Err(CosmosisError { kind: $err, reason: None })
+        }
+    };
+    ( $err:expr, $obj:expr, $( $fmt_arg:expr ),* ) => {
+        if $err == DATABLOCK_STATUS::DBS_SUCCESS {
+            Ok($obj)
+        } else {
+            Err(CosmosisError { kind: $err, reason: Some(format!($( $fmt_arg ),*)) })
+        }
+    }
+}
+
 mod bindings;
 pub use bindings::root::{DATABLOCK_STATUS, datablock_type_t};
 pub use bindings::root::__BindgenComplex as Complex;
 
+pub mod names;
+
+mod key;
+pub use key::{AsKey, Key, SectionName};
+use key::with_keys;
+
+mod value;
+pub use value::Value;
+
+mod ndarray;
+pub use ndarray::NdArray;
+
+#[macro_use]
+mod diff;
+pub use diff::{BlockDiff, Difference, ElementDiff, Tolerance};
+
+mod directory;
+
+mod json;
+pub use json::JsonWriter;
+
+mod npz;
+
+mod binary;
+
+mod hash;
+
+mod log;
+pub use log::{AccessReport, LogEntry, LogOperation};
+
+mod options;
+pub use options::{OptionValue, Options, OPTION_SECTION};
+
+mod ini;
+pub use ini::{Ini, IniEntry, IniError, IniLocation, IniSection, DEFAULT_SECTION};
+
+mod expression;
+pub use expression::{Expression, ExpressionError, Reference};
+mod constraints;
+pub use constraints::{Constraint, Constraints};
+
+mod parameters;
+pub use parameters::{DerivedParameter, Parameter, ParameterSpace};
+
+pub mod priors;
+pub use priors::{Prior, Priors};
+
+mod likelihoods;
+pub use likelihoods::{LikelihoodBreakdown, LikelihoodSelection};
+
+mod extra_output;
+pub use extra_output::{ExtraOutput, ExtraOutputs};
+
+mod profile;
+pub use profile::{ModuleProfile, PipelineProfile};
+
+mod snapshot;
+pub use snapshot::{DebugSnapshots, Snapshot, SnapshotFormat, PARAMETERS_STAGE};
+
+mod dataflow;
+pub use dataflow::{DataFlow, DataFlowWarning, StageAccesses};
+
+mod pipeline;
+pub use pipeline::{CosmosisModule, FunctionModule, Pipeline, PipelineResult};
+
+mod native;
+pub use native::NativeModule;
+
+pub mod section;
+pub use section::{CosmosisSection, SectionField};
+pub use cosmosis_derive::CosmosisSection;
+
 impl fmt::Display for DATABLOCK_STATUS {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -98,25 +195,30 @@ impl From<DATABLOCK_STATUS> for CosmosisError {
     }
 }
 
+/// I/O failures have no `DATABLOCK_STATUS` of their own, so are reported as `DBS_LOGIC_ERROR` with
+/// the underlying error as the reason.
+impl From<io::Error> for CosmosisError {
+    fn from(err: io::Error) -> Self {
+        CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(format!("I/O error: {}", err))
+    }
+}
+
 pub type CosmosisResult<T> = Result<T, CosmosisError>;
 
-macro_rules! wrap_cosmosis_result {
-    ( $err:expr, $obj:expr ) => {
-        if $err == DATABLOCK_STATUS::DBS_SUCCESS {
-            Ok($obj)
-        } else {
-            Err(CosmosisError { kind: $err, reason: None })
-        }
-    };
-    ( $err:expr, $obj:expr, $( $fmt_arg:expr ),* ) => {
-        if $err == DATABLOCK_STATUS::DBS_SUCCESS {
-            Ok($obj)
-        } else {
-            Err(CosmosisError { kind: $err, reason: Some(format!($( $fmt_arg ),*)) })
-        }
-    }
+/// Copies a string value for CosmoSIS, which stores strings NUL-terminated, so fails with
+/// `DBS_LOGIC_ERROR` if `s` contains a NUL byte.
+fn c_string(s: &str) -> CosmosisResult<CString> {
+    CString::new(s).map_err(|err| {
+        CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+            .with_reason(format!("String {:?} contains a NUL byte at position {}", s, err.nul_position()))
+    })
 }
 
+/// Metadata keys which serialization formats look up for every entry. The CosmoSIS C API can only
+/// fetch metadata by key, not list it, so metadata under any other key is not exported unless the
+/// writer is told to look for it.
+pub const DEFAULT_METADATA_KEYS: &[&str] = &["unit", "comment", "description"];
+
 /// CosmoSIS Data Storage block, all input parameters and outputs are passed through
 /// DataBlocks.
 pub struct DataBlock {
@@ -147,78 +249,286 @@ impl Drop for DataBlock {
     }
 }
 
+/// Two `DataBlock`s are equal if they have the same sections, the same names within each section,
+/// and the same type and value for each entry. Entries of types without a `Value` representation
+/// (string arrays) cannot be read, so, as in `diff`, they are compared by type and length only.
+impl PartialEq for DataBlock {
+    fn eq(&self, other: &DataBlock) -> bool {
+        let mut sections = self.sections();
+        let mut other_sections = other.sections();
+        sections.sort();
+        other_sections.sort();
+        if sections != other_sections {
+            return false;
+        }
+
+        sections.iter().all(|section| {
+            let mut names = self.names(section).unwrap_or_default();
+            let mut other_names = other.names(section).unwrap_or_default();
+            names.sort();
+            other_names.sort();
+            names == other_names && names.iter().all(|name| {
+                self.get_type(section, name) == other.get_type(section, name) &&
+                    match (self.get_value(section, name), other.get_value(section, name)) {
+                        (Ok(a), Ok(b)) => a == b,
+                        _ => self.array_length(section, name) == other.array_length(section, name)
+                    }
+            })
+        })
+    }
+}
+
+struct SectionDebug<'a>(&'a DataBlock, &'a str);
+
+impl<'a> fmt::Debug for SectionDebug<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = f.debug_map();
+        for name in self.0.names(self.1).unwrap_or_default() {
+            match self.0.get_value(self.1, &name) {
+                Ok(value) => map.entry(&name, &value),
+                Err(_) => map.entry(&name, &self.0.get_type(self.1, &name).unwrap_or(datablock_type_t::DBT_UNKNOWN))
+            };
+        }
+        map.finish()
+    }
+}
+
+impl fmt::Debug for DataBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sections = self.sections();
+        f.debug_map()
+         .entries(sections.iter().map(|section| (section, SectionDebug(self, section))))
+         .finish()
+    }
+}
+
+/// Pretty-prints the contents in an ini-like layout, one `[section]` header followed by one
+/// `name = value` line per entry. Long arrays are abbreviated.
+impl fmt::Display for DataBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, section) in self.sections().iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", section)?;
+            for name in self.names(section).unwrap_or_default() {
+                match self.get_value(section, &name) {
+                    Ok(value) => writeln!(f, "{} = {}", name, value)?,
+                    Err(_) => writeln!(f, "{} = <{:?}>", name,
+                                       self.get_type(section, &name).unwrap_or(datablock_type_t::DBT_UNKNOWN))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 impl DataBlock {
     pub fn new() -> Self {
         Default::default()
     }
 
+    /// The names of all sections in this `DataBlock`.
+    pub fn sections(&self) -> Vec<String> {
+        let count = unsafe { bindings::root::c_datablock_num_sections(self.ptr) };
+        (0..count).filter_map(|i| {
+            let name = unsafe { bindings::root::c_datablock_get_section_name(self.ptr, i) };
+            if name.is_null() {
+                None
+            } else {
+                Some(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+            }
+        }).collect()
+    }
+
+    /// The names of all values in `section`. Fails with `DBS_SECTION_NOT_FOUND` if there is no
+    /// such section.
+    pub fn names(&self, section: impl AsKey) -> CosmosisResult<Vec<String>> {
+        section.try_with_cstr(|section| {
+            if !self.contains_section(section) {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_SECTION_NOT_FOUND)
+                                         .with_reason(format!("No section: {}", section.to_string_lossy())));
+            }
+            let count = unsafe { bindings::root::c_datablock_num_values(self.ptr, section.as_ptr()) };
+            Ok((0..count).filter_map(|j| {
+                let name = unsafe { bindings::root::c_datablock_get_value_name(self.ptr, section.as_ptr(), j) };
+                if name.is_null() {
+                    None
+                } else {
+                    Some(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+                }
+            }).collect())
+        })?
+    }
+
+    /// Retrieve a value of whatever type is stored at `(section, name)`. Fails with
+    /// `DBS_WRONG_VALUE_TYPE` if that type has no `Value` representation.
+    pub fn get_value(&self, section: impl AsKey, name: impl AsKey) -> CosmosisResult<Value> {
+        with_keys(&section, &name, |section, name| Value::get_datablock(self, section, name))
+    }
+
+    /// Store a new `Value` in a DataBlock. Fails if an entry already exists for `(section, name)`.
+    pub fn put_value(&mut self, section: impl AsKey, name: impl AsKey, value: &Value) -> CosmosisResult<()> {
+        with_keys(&section, &name, |section, name| value.put_datablock(self, section, name))
+    }
+
+    /// Retrieve the metadata string stored under `key` for the entry `(section, name)`.
+    pub fn get_metadata(&self, section: impl AsKey, name: impl AsKey, key: impl AsKey) -> CosmosisResult<String> {
+        with_keys(&section, &name, |section, name| key.try_with_cstr(|key| {
+            let mut cstr: *mut raw::c_char = std::ptr::null_mut();
+            let retval = unsafe {
+                bindings::root::c_datablock_get_metadata(self.ptr, section.as_ptr(), name.as_ptr(),
+                                                         key.as_ptr(), &mut cstr)
+            };
+            wrap_cosmosis_result!(retval,
+                unsafe {
+                    // As with string values, copy into Rust's heap before freeing C's copy
+                    let output_string = CStr::from_ptr(cstr).to_string_lossy().into_owned();
+                    libc::free(cstr as *mut libc::c_void);
+                    output_string
+                },
+                "Could not get metadata {:?} at (section, name): ({}, {})", key,
+                section.to_string_lossy(), name.to_string_lossy())
+        })?)
+    }
+
+    /// Attach a new metadata string under `key` to the existing entry `(section, name)`. Fails if
+    /// that entry already has metadata under `key`.
+    pub fn put_metadata(&mut self, section: impl AsKey, name: impl AsKey, key: impl AsKey, value: &str) -> CosmosisResult<()> {
+        let value = c_string(value)?;
+        with_keys(&section, &name, |section, name| key.try_with_cstr(|key| {
+            let retval = unsafe {
+                bindings::root::c_datablock_put_metadata(self.ptr, section.as_ptr(), name.as_ptr(),
+                                                         key.as_ptr(), value.as_ptr())
+            };
+            wrap_cosmosis_result!(retval, (), "Could not put metadata {:?} at (section, name): ({}, {})", key,
+                                  section.to_string_lossy(), name.to_string_lossy())
+        })?)
+    }
+
+    /// Replace the metadata string under `key` for the entry `(section, name)`.
+    pub fn replace_metadata(&mut self, section: impl AsKey, name: impl AsKey, key: impl AsKey, value: &str) -> CosmosisResult<()> {
+        let value = c_string(value)?;
+        with_keys(&section, &name, |section, name| key.try_with_cstr(|key| {
+            let retval = unsafe {
+                bindings::root::c_datablock_replace_metadata(self.ptr, section.as_ptr(), name.as_ptr(),
+                                                             key.as_ptr(), value.as_ptr())
+            };
+            wrap_cosmosis_result!(retval, (), "Could not replace metadata {:?} at (section, name): ({}, {})", key,
+                                  section.to_string_lossy(), name.to_string_lossy())
+        })?)
+    }
+
+    /// The metadata attached to `(section, name)` under each of `keys`, skipping keys which are
+    /// not set.
+    pub(crate) fn metadata_for<K>(&self, section: &str, name: &str, keys: &[K]) -> Vec<(String, String)>
+        where K: AsRef<str> {
+        keys.iter()
+            .map(|key| key.as_ref())
+            .filter_map(|key| self.get_metadata(section, name, key).ok().map(|v| (key.to_string(), v)))
+            .collect()
+    }
+
     /// Whether or not the datablock contains a value `name` in the section
     /// `section`.
-    pub fn contains(&self, section: &str, name: &str) -> bool {
-        unsafe {
-            bindings::root::c_datablock_has_value(self.ptr,
-                                                 CString::new(section).unwrap().as_ptr(),
-                                                 CString::new(name).unwrap().as_ptr())
-        }
+    pub fn contains(&self, section: impl AsKey, name: impl AsKey) -> bool {
+        with_keys(&section, &name, |section, name| {
+            Ok(unsafe { bindings::root::c_datablock_has_value(self.ptr, section.as_ptr(), name.as_ptr()) })
+        }).unwrap_or(false)
     }
 
     /// Whether or not this `DataBlock` contains a section of the given name.
-    pub fn contains_section(&self, section: &str) -> bool {
-        unsafe {
-            bindings::root::c_datablock_has_section(self.ptr,
-                                                    CString::new(section).unwrap().as_ptr())
-        }
+    pub fn contains_section(&self, section: impl AsKey) -> bool {
+        section.try_with_cstr(|section| unsafe {
+            bindings::root::c_datablock_has_section(self.ptr, section.as_ptr())
+        }).unwrap_or(false)
     }
 
     /// Returns the type of the DataBlock entry, or `None` if no such entry exists.
-    pub fn get_type(&self, section: &str, name: &str) -> Option<datablock_type_t> {
+    pub fn get_type(&self, section: impl AsKey, name: impl AsKey) -> Option<datablock_type_t> {
         let mut ty: datablock_type_t = datablock_type_t::DBT_UNKNOWN;
-        let result = unsafe {
-            bindings::root::c_datablock_get_type(self.ptr,
-                                                 CString::new(section).unwrap().as_ptr(),
-                                                 CString::new(name).unwrap().as_ptr(),
-                                                 &mut ty)
-        };
-        if result == DATABLOCK_STATUS::DBS_NAME_NOT_FOUND {
+        let result = with_keys(&section, &name, |section, name| {
+            Ok(unsafe { bindings::root::c_datablock_get_type(self.ptr, section.as_ptr(), name.as_ptr(), &mut ty) })
+        });
+        if result.ok() == Some(DATABLOCK_STATUS::DBS_SUCCESS) {
+            Some(ty)
+        } else {
+            None
+        }
+    }
+
+    /// The number of elements in the 1D array at `(section, name)`, or `None` if there is no such
+    /// array.
+    fn array_length(&self, section: &str, name: &str) -> Option<usize> {
+        let length = with_keys(section, name, |section, name| {
+            Ok(unsafe { bindings::root::c_datablock_get_array_length(self.ptr, section.as_ptr(), name.as_ptr()) })
+        }).ok()?;
+        if length < 0 {
             None
         } else {
-            Some(ty)
+            Some(length as usize)
         }
     }
 
     /// Whether or not the `DataBlock` contains an entry with the given `section`
     /// and `name`, of the type `C`. If the entry is of a different type, or if
     /// the there is no such entry, returns false.
-    pub fn is_type<C: CosmosisGettable>(&self, section: &str, name: &str) -> bool {
-        self.get_type(section, name).map(|t| t == C::InternalType::cosmosis_type()).unwrap_or(false)
+    pub fn is_type<C: CosmosisGettable>(&self, section: impl AsKey, name: impl AsKey) -> bool {
+        self.get_type(section, name).map(C::InternalType::has_cosmosis_type).unwrap_or(false)
     }
 
     /// Retrieve a value from a DataBlock.
-    pub fn get<T>(&self, section: &str, name: &str) -> CosmosisResult<T>
+    pub fn get<T>(&self, section: impl AsKey, name: impl AsKey) -> CosmosisResult<T>
         where T: CosmosisGettable {
-        T::get_datablock(self, section, name)
+        with_keys(&section, &name, |section, name| T::get_datablock(self, section, name))
     }
 
     /// Stores the given object into the `DataBlock`, associated with the given section and name.
     /// If an object is already stored (of the same type) in that name, replaces and returns that
     /// previous value; if the name does not exist already in the `DataBlock`, creates a new entry.
-    pub fn insert<T, I>(&mut self, section: &str, name: &str, obj: I) -> CosmosisResult<Option<T::ResultType>>
+    pub fn insert<T, I>(&mut self, section: impl AsKey, name: impl AsKey, obj: I) -> CosmosisResult<Option<T::ResultType>>
         where T: CosmosisStorable,
               I: Borrow<T> {
-        if self.contains(section, name) {
-            T::replace_datablock(self, section, name, obj.borrow())
-               .map(|s| Some(s))
-        } else {
-            T::put_datablock(self, section, name, obj.borrow())
-               .map(|()| None)
-        }
+        with_keys(&section, &name, |section, name| {
+            if self.contains(section, name) {
+                T::replace_datablock(self, section, name, obj.borrow())
+                   .map(Some)
+            } else {
+                T::put_datablock(self, section, name, obj.borrow())
+                   .map(|()| None)
+            }
+        })
+    }
+
+    /// Replaces an existing value without reading back the previous one, which avoids a full copy
+    /// of large arrays. Unlike `insert`, fails with `DBS_NAME_NOT_FOUND` if there is no entry for
+    /// `(section, name)`, and with `DBS_WRONG_VALUE_TYPE` if the existing entry is of another type.
+    pub fn overwrite<T, I>(&mut self, section: impl AsKey, name: impl AsKey, obj: I) -> CosmosisResult<()>
+        where T: CosmosisStorable + ?Sized,
+              I: Borrow<T> {
+        with_keys(&section, &name, |section, name| T::overwrite_datablock(self, section, name, obj.borrow()))
     }
 
     /// Store a new value in a DataBlock. Fails if an entry already exists for `(section, name)`.
-    pub fn put<T, I>(&mut self, section: &str, name: &str, obj: I) -> CosmosisResult<()>
+    pub fn put<T, I>(&mut self, section: impl AsKey, name: impl AsKey, obj: I) -> CosmosisResult<()>
+        where T: CosmosisStorable + ?Sized,
+              I: Borrow<T> {
+        with_keys(&section, &name, |section, name| T::put_datablock(self, section, name, obj.borrow()))
+    }
+
+    /// Stores a value whether or not an entry already exists for `(section, name)`, replacing an
+    /// existing one as `overwrite` does, without reading it back. Fails with
+    /// `DBS_WRONG_VALUE_TYPE` if the existing entry is of another type.
+    pub fn put_or_overwrite<T, I>(&mut self, section: impl AsKey, name: impl AsKey, obj: I) -> CosmosisResult<()>
         where T: CosmosisStorable + ?Sized,
               I: Borrow<T> {
-        T::put_datablock(self, section, name, obj.borrow())
+        with_keys(&section, &name, |section, name| {
+            if self.contains(section, name) {
+                T::overwrite_datablock(self, section, name, obj.borrow())
+            } else {
+                T::put_datablock(self, section, name, obj.borrow())
+            }
+        })
     }
 }
 
@@ -226,20 +536,25 @@ impl DataBlock {
 pub trait CosmosisDataType: Sized {
     type InsertRepr: ?Sized;
     fn cosmosis_type() -> datablock_type_t;
-    fn direct_get_datablock(&DataBlock, section: &str, name: &str) -> CosmosisResult<Self>;
-    fn direct_put_datablock(&mut DataBlock, section: &str, name: &str, obj: &Self::InsertRepr) -> CosmosisResult<()>;
-    fn direct_replace_datablock(&mut DataBlock, section: &str, name: &str, obj: &Self::InsertRepr) -> CosmosisResult<Self>;
+    /// Whether entries tagged `ty` are read as this type; by default, only those tagged `cosmosis_type()`.
+    fn has_cosmosis_type(ty: datablock_type_t) -> bool {
+        ty == Self::cosmosis_type()
+    }
+    fn direct_get_datablock(&DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self>;
+    fn direct_put_datablock(&mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<()>;
+    fn direct_replace_datablock(&mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<Self>;
+    fn direct_overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<()>;
 }
 
 /// Represents types which may be retrieved from a `DataBlock`.
 pub trait CosmosisGettable: Sized {
     type InternalType: CosmosisDataType;
-    fn get_datablock(&DataBlock, section: &str, name: &str) -> CosmosisResult<Self>;
+    fn get_datablock(&DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self>;
 }
 
 impl<T> CosmosisGettable for T where T: CosmosisDataType {
     type InternalType = Self;
-    fn get_datablock(db: &DataBlock, section: &str, name: &str) -> CosmosisResult<Self> {
+    fn get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
         Self::direct_get_datablock(db, section, name)
     }
 }
@@ -248,19 +563,23 @@ impl<T> CosmosisGettable for T where T: CosmosisDataType {
 pub trait CosmosisStorable {
     type InternalType: CosmosisDataType;
     type ResultType: CosmosisGettable;
-    fn put_datablock(&mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<()>;
-    fn replace_datablock(&mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<Self::ResultType>;
+    fn put_datablock(&mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()>;
+    fn replace_datablock(&mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<Self::ResultType>;
+    fn overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()>;
 }
 
 impl<T> CosmosisStorable for T where T: CosmosisDataType<InsertRepr=T> {
     type InternalType = Self;
     type ResultType = Self;
-    fn put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<()> {
+    fn put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
         Self::direct_put_datablock(db, section, name, obj)
     }
-    fn replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<Self> {
+    fn replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<Self> {
         Self::direct_replace_datablock(db, section, name, obj)
     }
+    fn overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
+        Self::direct_overwrite_datablock(db, section, name, obj)
+    }
 }
 
 macro_rules! gen_cosmosis_data_type {
@@ -273,39 +592,43 @@ macro_rules! gen_cosmosis_data_type {
                 datablock_type_t::$cosmo_name
             }
 
-            fn direct_get_datablock(db: &DataBlock, section: &str, name: &str) -> CosmosisResult<Self> {
+            fn direct_get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
                 let mut n: Self = $default_val;
                 let retval = unsafe {
                     $getter(db.ptr,
-                            CString::new(section).unwrap().as_ptr(),
-                            CString::new(name).unwrap().as_ptr(),
+                            section.as_ptr(),
+                            name.as_ptr(),
                             &mut n)
                 };
                 wrap_cosmosis_result!(retval, n, "Could not get value at (section, name): ({}, {})",
-                                      section, name)
+                                      section.to_string_lossy(), name.to_string_lossy())
             }
 
-            fn direct_put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &$rust_name) -> CosmosisResult<()> {
+            fn direct_put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &$rust_name) -> CosmosisResult<()> {
                 let retval = unsafe {
                     $putter(db.ptr,
-                            CString::new(section).unwrap().as_ptr(),
-                            CString::new(name).unwrap().as_ptr(),
+                            section.as_ptr(),
+                            name.as_ptr(),
                             *obj)
                 };
                 wrap_cosmosis_result!(retval, (), "Could not put value at (section, name): ({}, {})",
-                                      section, name)
+                                      section.to_string_lossy(), name.to_string_lossy())
             }
 
-            fn direct_replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &$rust_name) -> CosmosisResult<Self> {
+            fn direct_replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &$rust_name) -> CosmosisResult<Self> {
                 let result = Self::direct_get_datablock(db, section, name)?;
+                Self::direct_overwrite_datablock(db, section, name, obj).map(|()| result)
+            }
+
+            fn direct_overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &$rust_name) -> CosmosisResult<()> {
                 let retval = unsafe {
                     $replacer(db.ptr,
-                              CString::new(section).unwrap().as_ptr(),
-                              CString::new(name).unwrap().as_ptr(),
+                              section.as_ptr(),
+                              name.as_ptr(),
                               *obj)
                 };
-                wrap_cosmosis_result!(retval, result, "Could not get value at (section, name): ({}, {})",
-                                      section, name)
+                wrap_cosmosis_result!(retval, (), "Could not replace value at (section, name): ({}, {})",
+                                      section.to_string_lossy(), name.to_string_lossy())
             }
         }
     }
@@ -338,73 +661,81 @@ macro_rules! gen_cosmosis_vector_type {
                 datablock_type_t::$cosmo_name
             }
 
-            fn direct_get_datablock(db: &DataBlock, section: &str, name: &str) -> CosmosisResult<Self> {
+            fn direct_get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
                 let mut size = unsafe {
                     bindings::root::c_datablock_get_array_length(db.ptr,
-                                                                 CString::new(section).unwrap().as_ptr(),
-                                                                 CString::new(name).unwrap().as_ptr())
+                                                                 section.as_ptr(),
+                                                                 name.as_ptr())
                 };
                 if size < 0 {
                     if db.contains(section, name) {
                         Err(CosmosisError::new(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE)
                                           .with_reason(format!("Not a 1D Double array at (section, name): ({}, {})",
-                                                               section, name)))
+                                                               section.to_string_lossy(), name.to_string_lossy())))
                     } else {
                         Err(CosmosisError::new(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND)
                                           .with_reason(format!("No value at (section, name): ({}, {})",
-                                                               section, name)))
+                                                               section.to_string_lossy(), name.to_string_lossy())))
                     }
                 } else {
                     let mut vec = Vec::with_capacity(size as usize);
                     let retval = unsafe {
                         vec.set_len(size as usize);
                         $getter(db.ptr,
-                                CString::new(section).unwrap().as_ptr(),
-                                CString::new(name).unwrap().as_ptr(),
+                                section.as_ptr(),
+                                name.as_ptr(),
                                 vec.as_mut_ptr(),
                                 &mut size,
                                 size)
                     };
                     wrap_cosmosis_result!(retval, vec,
-                                          "Could not get value at (section, name): ({}, {})", section, name)
+                                          "Could not get value at (section, name): ({}, {})",
+                                          section.to_string_lossy(), name.to_string_lossy())
                 }
             }
 
-            fn direct_put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self::InsertRepr) -> CosmosisResult<()> {
+            fn direct_put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<()> {
                 let retval = unsafe {
                     $putter(db.ptr,
-                            CString::new(section).unwrap().as_ptr(),
-                            CString::new(name).unwrap().as_ptr(),
+                            section.as_ptr(),
+                            name.as_ptr(),
                             obj.as_ptr(),
                             obj.len() as raw::c_int)
                 };
                 wrap_cosmosis_result!(retval, (), "Could not put value at (section, name): ({}, {})",
-                                      section, name)
+                                      section.to_string_lossy(), name.to_string_lossy())
             }
 
-            fn direct_replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self::InsertRepr) -> CosmosisResult<Self> {
+            fn direct_replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<Self> {
                 let result = Self::direct_get_datablock(db, section, name)?;
+                Self::direct_overwrite_datablock(db, section, name, obj).map(|()| result)
+            }
+
+            fn direct_overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self::InsertRepr) -> CosmosisResult<()> {
                 let retval = unsafe {
                     $replacer(db.ptr,
-                              CString::new(section).unwrap().as_ptr(),
-                              CString::new(name).unwrap().as_ptr(),
+                              section.as_ptr(),
+                              name.as_ptr(),
                               obj.as_ptr(),
                               obj.len() as raw::c_int)
                 };
-                wrap_cosmosis_result!(retval, result, "Could not replace value at (section, name): ({}, {})",
-                                      section, name)
+                wrap_cosmosis_result!(retval, (), "Could not replace value at (section, name): ({}, {})",
+                                      section.to_string_lossy(), name.to_string_lossy())
             }
         }
 
         impl CosmosisStorable for [$rust_name] {
             type InternalType = Vec<$rust_name>;
             type ResultType = Vec<$rust_name>;
-            fn put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<()> {
+            fn put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
                 Self::InternalType::direct_put_datablock(db, section, name, obj)
             }
-            fn replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &Self) -> CosmosisResult<Self::ResultType> {
+            fn replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<Self::ResultType> {
                 Self::InternalType::direct_replace_datablock(db, section, name, obj)
             }
+            fn overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
+                Self::InternalType::direct_overwrite_datablock(db, section, name, obj)
+            }
         }
     }
 }
@@ -429,12 +760,12 @@ impl CosmosisDataType for CString {
         datablock_type_t::DBT_STRING
     }
 
-    fn direct_get_datablock(db: &DataBlock, section: &str, name: &str) -> CosmosisResult<Self> {
+    fn direct_get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
         let mut cstr: *mut raw::c_char = std::ptr::null_mut();
         let retval = unsafe {
             bindings::root::c_datablock_get_string(db.ptr,
-                                                   CString::new(section).unwrap().as_ptr(),
-                                                   CString::new(name).unwrap().as_ptr(),
+                                                   section.as_ptr(),
+                                                   name.as_ptr(),
                                                    &mut cstr)
         };
         wrap_cosmosis_result!(retval, 
@@ -448,36 +779,42 @@ impl CosmosisDataType for CString {
                 libc::free(cstr as *mut libc::c_void);
                 output_string
             },
-            "Could not get value at (section, name): ({}, {})", section, name)
+            "Could not get value at (section, name): ({}, {})",
+            section.to_string_lossy(), name.to_string_lossy())
     }
 
-    fn direct_put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &CStr) -> CosmosisResult<()> {
+    fn direct_put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &CStr) -> CosmosisResult<()> {
         let retval = unsafe {
             bindings::root::c_datablock_put_string(db.ptr,
-                                                   CString::new(section).unwrap().as_ptr(),
-                                                   CString::new(name).unwrap().as_ptr(),
+                                                   section.as_ptr(),
+                                                   name.as_ptr(),
                                                    obj.as_ptr())
         };
         wrap_cosmosis_result!(retval, (), "Could not put value at (section, name): ({}, {})",
-                              section, name)
+                              section.to_string_lossy(), name.to_string_lossy())
     }
 
-    fn direct_replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &CStr) -> CosmosisResult<Self> {
+    fn direct_replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &CStr) -> CosmosisResult<Self> {
         let result = Self::direct_get_datablock(db, section, name)?;
+        Self::direct_overwrite_datablock(db, section, name, obj).map(|()| result)
+    }
+
+    fn direct_overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &CStr) -> CosmosisResult<()> {
         let retval = unsafe {
             bindings::root::c_datablock_replace_string(db.ptr,
-                                                       CString::new(section).unwrap().as_ptr(),
-                                                       CString::new(name).unwrap().as_ptr(),
+                                                       section.as_ptr(),
+                                                       name.as_ptr(),
                                                        obj.as_ptr())
         };
-        wrap_cosmosis_result!(retval, result,
-                              "Could not replace value at (section, name): ({}, {})", section, name)
+        wrap_cosmosis_result!(retval, (),
+                              "Could not replace value at (section, name): ({}, {})",
+                              section.to_string_lossy(), name.to_string_lossy())
     }
 }
 
 impl CosmosisGettable for String {
     type InternalType = CString;
-    fn get_datablock(db: &DataBlock, section: &str, name: &str) -> CosmosisResult<Self> {
+    fn get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
         CString::direct_get_datablock(db, section, name)
                 .map(|cstr| cstr.into_string().expect("DataBlock should contain valid UTF-8"))
     }
@@ -487,19 +824,24 @@ impl CosmosisStorable for str {
     type InternalType = CString;
     type ResultType = String;
 
-    fn put_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &str) -> CosmosisResult<()> {
-        CString::direct_put_datablock(db, section, name, &CString::new(obj).unwrap())
+    fn put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &str) -> CosmosisResult<()> {
+        CString::direct_put_datablock(db, section, name, &c_string(obj)?)
     }
 
-    fn replace_datablock(db: &mut DataBlock, section: &str, name: &str, obj: &str) -> CosmosisResult<String> {
-        CString::direct_replace_datablock(db, section, name, &CString::new(obj).unwrap())
+    fn replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &str) -> CosmosisResult<String> {
+        CString::direct_replace_datablock(db, section, name, &c_string(obj)?)
                 .map(|cstr| cstr.into_string().expect("DataBlock should contain valid UTF-8"))
     }
+
+    fn overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &str) -> CosmosisResult<()> {
+        CString::direct_overwrite_datablock(db, section, name, &c_string(obj)?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DataBlock, DATABLOCK_STATUS, datablock_type_t};
+    use super::{bindings, DataBlock, Key, NdArray, Value, DATABLOCK_STATUS, datablock_type_t};
+    use std::ffi::CString;
     use std::os::raw;
 
     #[test]
@@ -566,6 +908,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_overwrite() {
+        let mut db = DataBlock::new();
+        assert!(db.put::<[f64], _>("my_section", "z", &[0.0, 0.5, 1.0][..]).is_ok());
+        assert!(db.put("my_section", "n", 3 as raw::c_int).is_ok());
+
+        assert!(db.overwrite::<[f64], _>("my_section", "z", &[0.0, 1.0][..]).is_ok());
+        assert_eq!(db.get::<Vec<f64>>("my_section", "z").unwrap(), vec![0.0, 1.0]);
+
+        assert_eq!(db.overwrite("my_section", "n", 2.0).unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+        assert_eq!(db.overwrite("my_section", "missing", 2 as raw::c_int).unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_NAME_NOT_FOUND);
+        assert_eq!(db.get::<raw::c_int>("my_section", "n").unwrap(), 3);
+
+        assert!(db.put_or_overwrite("my_section", "missing", 2 as raw::c_int).is_ok());
+        assert!(db.put_or_overwrite("my_section", "n", 4 as raw::c_int).is_ok());
+        assert_eq!(db.get::<raw::c_int>("my_section", "n").unwrap(), 4);
+        assert_eq!(db.put_or_overwrite("my_section", "n", 2.0).unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+    }
+
     #[test]
     fn test_put_get_vec() {
         let mut db = DataBlock::new();
@@ -604,4 +968,94 @@ mod tests {
             assert_eq!(db.get::<f64>("my_section", name).unwrap_err().kind, DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
         }
     }
+
+    #[test]
+    fn test_nul_bytes() {
+        let mut db = DataBlock::new();
+        db.put::<str, _>("my_section", "a", "text").unwrap();
+        assert_eq!(db.put::<str, _>("my_section", "nul", "a\0b").unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!(db.overwrite::<str, _>("my_section", "a", "a\0b").unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!(db.put_metadata("my_section", "a", "unit", "\0").unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(!db.contains("my_section", "nul"));
+
+        assert_eq!(db.put("my\0section", "x", 1.0).unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!(db.get::<f64>("my_section", "x\0").unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!(db.get_metadata("my_section", "a", "un\0it").unwrap_err().kind,
+                   DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert_eq!(db.names("my\0section").unwrap_err().kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(!db.contains("my_section", "a\0") && !db.contains_section("my\0section"));
+        assert_eq!(db.get_type("my_section", "a\0"), None);
+    }
+
+    #[test]
+    fn test_key_types() {
+        let mut db = DataBlock::new();
+        let section = Key::new("cosmological_parameters").unwrap();
+        let omega_m = Key::new("omega_m").unwrap();
+        let h0 = CString::new("h0").unwrap();
+
+        assert!(db.put(&section, &omega_m, 0.3).is_ok());
+        assert!(db.put(&section, h0.as_c_str(), 0.7).is_ok());
+
+        assert!(db.contains_section(&section));
+        assert_eq!(db.get::<f64>("cosmological_parameters", "omega_m").unwrap(), 0.3);
+        assert_eq!(db.get::<f64>(&section, &h0).unwrap(), 0.7);
+        assert_eq!(db.insert(&section, &omega_m, 0.25).unwrap(), Some(0.3));
+        assert_eq!(db.get_type(&section, "h0").unwrap(), datablock_type_t::DBT_DOUBLE);
+    }
+
+    #[test]
+    fn test_enumerate_and_compare() {
+        let mut db = DataBlock::new();
+        assert!(db.put("cosmological_parameters", "omega_m", 0.3).is_ok());
+        assert!(db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).is_ok());
+        assert!(db.put::<[f64], _>("distances", "z", &[0.0, 0.5, 1.0][..]).is_ok());
+
+        let mut sections = db.sections();
+        sections.sort();
+        assert_eq!(sections, vec!["cosmological_parameters", "distances"]);
+        assert_eq!(db.names("distances").unwrap(), vec!["z"]);
+        assert_eq!(db.names("nowhere").unwrap_err().kind, DATABLOCK_STATUS::DBS_SECTION_NOT_FOUND);
+        assert_eq!(db.get_value("distances", "z").unwrap(), Value::DoubleArray(vec![0.0, 0.5, 1.0]));
+
+        let mut other = db.clone();
+        assert_eq!(db, other);
+        other.insert("cosmological_parameters", "omega_m", 0.31).unwrap();
+        assert!(db != other);
+        other.insert("cosmological_parameters", "omega_m", 0.3).unwrap();
+        other.put("distances", "h", 0.7).unwrap();
+        assert!(db != other);
+
+        db.put("distances", "cov", NdArray::new(vec![2, 2], vec![1.0, 0.0, 0.0, 1.0]).unwrap()).unwrap();
+        let mut other = db.clone();
+        assert_eq!(db, other);
+        other.overwrite("distances", "cov", NdArray::new(vec![2, 2], vec![1.0, 0.5, 0.5, 1.0]).unwrap()).unwrap();
+        assert!(db != other);
+
+        let display = db.to_string();
+        assert!(display.contains("[distances]\n") && display.contains("\nz = [0, 0.5, 1]\n"), "{}", display);
+        assert!(display.contains("\ncov = [1, 0, 0, 1] (shape 2x2)\n"), "{}", display);
+        assert!(format!("{:?}", db).contains("\"omega_m\": Double(0.3)"));
+    }
+
+    #[test]
+    fn test_compare_string_array() {
+        let db = DataBlock::new();
+        let (section, name) = (CString::new("labels").unwrap(), CString::new("names").unwrap());
+        let labels = [CString::new("a").unwrap(), CString::new("b").unwrap()];
+        let mut ptrs: Vec<_> = labels.iter().map(|label| label.as_ptr()).collect();
+        let status = unsafe {
+            bindings::root::c_datablock_put_string_array_1d(db.ptr, section.as_ptr(), name.as_ptr(),
+                                                            ptrs.as_mut_ptr() as _, ptrs.len() as raw::c_int)
+        };
+        assert_eq!(status, DATABLOCK_STATUS::DBS_SUCCESS);
+        assert!(db.get_value("labels", "names").is_err());
+        assert_eq!(db, db);
+
+        let mut other = DataBlock::new();
+        other.put::<[f64], _>("labels", "names", &[0.0, 1.0][..]).unwrap();
+        assert!(db != other);
+    }
 }
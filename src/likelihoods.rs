@@ -0,0 +1,121 @@
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use names::{LIKELIHOODS_SECTION, LIKELIHOOD_SUFFIX};
+
+/// Which entries of `LIKELIHOODS_SECTION` make up the total likelihood.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum LikelihoodSelection {
+    /// Every `*_like` entry the modules wrote.
+    #[default]
+    AllFound,
+    /// Exactly these likelihoods, named with or without `LIKELIHOOD_SUFFIX`. Each must be present.
+    Named(Vec<String>)
+}
+
+impl LikelihoodSelection {
+    /// Reads the pipeline's `likelihoods` option, a list separated by whitespace or commas.
+    /// As in CosmoSIS, a missing option means `AllFound`, and an empty one means no likelihoods.
+    pub fn from_option(option: Option<&str>) -> Self {
+        match option {
+            Some(names) => LikelihoodSelection::Named(names.split(|c: char| c == ',' || c.is_whitespace())
+                                                           .filter(|name| !name.is_empty())
+                                                           .map(str::to_string)
+                                                           .collect()),
+            None => LikelihoodSelection::AllFound
+        }
+    }
+}
+
+/// The total log-likelihood, and the components it is summed from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LikelihoodBreakdown {
+    pub total: f64,
+    /// `(name, log-likelihood)` pairs, named without `LIKELIHOOD_SUFFIX`.
+    pub components: Vec<(String, f64)>
+}
+
+impl LikelihoodBreakdown {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let name = name.strip_suffix(LIKELIHOOD_SUFFIX).unwrap_or(name);
+        self.components.iter().find(|&(component, _)| component.eq_ignore_ascii_case(name)).map(|&(_, like)| like)
+    }
+}
+
+impl DataBlock {
+    /// Sums the log-likelihoods in `LIKELIHOODS_SECTION` picked out by `selection`.
+    ///
+    /// A named likelihood which no module wrote is a `DBS_NAME_NOT_FOUND` error, and a NaN
+    /// component a `DBS_LOGIC_ERROR`, each naming the likelihood. `-inf` is a valid result.
+    pub fn likelihoods(&self, selection: &LikelihoodSelection) -> CosmosisResult<LikelihoodBreakdown> {
+        let names = match *selection {
+            LikelihoodSelection::AllFound => {
+                if !self.contains_section(LIKELIHOODS_SECTION) {
+                    return Ok(LikelihoodBreakdown::default());
+                }
+                let mut found: Vec<_> = self.names(LIKELIHOODS_SECTION)?.into_iter()
+                                            .filter_map(|name| name.strip_suffix(LIKELIHOOD_SUFFIX).map(str::to_string))
+                                            .collect();
+                found.sort();
+                found
+            },
+            LikelihoodSelection::Named(ref names) => {
+                names.iter().map(|name| name.strip_suffix(LIKELIHOOD_SUFFIX).unwrap_or(name).to_string()).collect()
+            }
+        };
+
+        let mut breakdown = LikelihoodBreakdown::default();
+        for name in names {
+            let key = format!("{}{}", name, LIKELIHOOD_SUFFIX);
+            if !self.contains(LIKELIHOODS_SECTION, key.as_str()) {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND)
+                           .with_reason(format!("Likelihood {} was requested, but no module wrote {}/{}",
+                                                name, LIKELIHOODS_SECTION, key)));
+            }
+            let like = self.get::<f64>(LIKELIHOODS_SECTION, key.as_str()).map_err(|err| {
+                CosmosisError::new(err.kind)
+                    .with_reason(format!("Could not read likelihood {}/{}: {}", LIKELIHOODS_SECTION, key, err))
+            })?;
+            if like.is_nan() {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                           .with_reason(format!("Likelihood {} is NaN", name)));
+            }
+            breakdown.total += like;
+            breakdown.components.push((name, like));
+        }
+        Ok(breakdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LikelihoodSelection;
+    use {DataBlock, DATABLOCK_STATUS};
+
+    #[test]
+    fn test_likelihoods() {
+        let mut db = DataBlock::new();
+        assert_eq!(db.likelihoods(&LikelihoodSelection::AllFound).unwrap().total, 0.0);
+
+        db.put("likelihoods", "shear_like", -2.0).unwrap();
+        db.put("likelihoods", "planck_like", -3.0).unwrap();
+        db.put("likelihoods", "prior", -1.0).unwrap();
+
+        let all = db.likelihoods(&LikelihoodSelection::AllFound).unwrap();
+        assert_eq!(all.total, -5.0);
+        assert_eq!(all.components, vec![("planck".to_string(), -3.0), ("shear".to_string(), -2.0)]);
+        assert_eq!(all.get("shear_like"), Some(-2.0));
+
+        let selection = LikelihoodSelection::from_option(Some("shear_like"));
+        assert_eq!(db.likelihoods(&selection).unwrap().total, -2.0);
+        assert_eq!(db.likelihoods(&LikelihoodSelection::from_option(Some(""))).unwrap().total, 0.0);
+        assert_eq!(LikelihoodSelection::from_option(None), LikelihoodSelection::AllFound);
+
+        let err = db.likelihoods(&LikelihoodSelection::from_option(Some("planck, bao"))).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_NAME_NOT_FOUND);
+        assert!(err.to_string().contains("bao_like"), "{}", err);
+
+        db.put("likelihoods", "bao_like", f64::NAN).unwrap();
+        let err = db.likelihoods(&LikelihoodSelection::AllFound).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(err.to_string().contains("bao is NaN"), "{}", err);
+    }
+}
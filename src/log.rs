@@ -0,0 +1,274 @@
+use std::collections::BTreeSet;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw;
+
+use super::{bindings, AsKey, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use key::with_keys;
+
+/// Longest string, including the NUL terminator, copied out of each field of a log entry.
+const LOG_FIELD_LEN: usize = 256;
+
+/// Log type recorded by `DataBlock::clear_log`. Entries before the last such marker are hidden
+/// from `DataBlock::access_log`.
+const LOG_CLEARED: &str = "LOG-CLEARED";
+
+/// The kinds of access the CosmoSIS `DataBlock` logs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogOperation {
+    Read,
+    ReadFail,
+    /// A read of a missing value, for which the caller's default was used instead.
+    ReadDefault,
+    Write,
+    WriteFail,
+    Replace,
+    ReplaceFail,
+    Delete,
+    Clear,
+    /// Logged by the pipeline before running a module; the entry's section is the module's name.
+    ModuleStart,
+    /// Logged by the pipeline when a point violates a constraint; the entry's name is the constraint.
+    ConstraintFailed,
+    /// Any other log type, e.g. one added by a newer CosmoSIS.
+    Other(String)
+}
+
+impl LogOperation {
+    fn parse(log_type: &str) -> Self {
+        match log_type {
+            "READ-OK" => LogOperation::Read,
+            "READ-FAIL" => LogOperation::ReadFail,
+            "READ-DEFAULT" => LogOperation::ReadDefault,
+            "WRITE-OK" => LogOperation::Write,
+            "WRITE-FAIL" => LogOperation::WriteFail,
+            "REPLACE-OK" => LogOperation::Replace,
+            "REPLACE-FAIL" => LogOperation::ReplaceFail,
+            "DELETE" => LogOperation::Delete,
+            "CLEAR" => LogOperation::Clear,
+            "MODULE-START" => LogOperation::ModuleStart,
+            "CONSTRAINT-FAILED" => LogOperation::ConstraintFailed,
+            other => LogOperation::Other(other.to_string())
+        }
+    }
+
+    /// The log type as CosmoSIS records it, e.g. `READ-OK`.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            LogOperation::Read => "READ-OK",
+            LogOperation::ReadFail => "READ-FAIL",
+            LogOperation::ReadDefault => "READ-DEFAULT",
+            LogOperation::Write => "WRITE-OK",
+            LogOperation::WriteFail => "WRITE-FAIL",
+            LogOperation::Replace => "REPLACE-OK",
+            LogOperation::ReplaceFail => "REPLACE-FAIL",
+            LogOperation::Delete => "DELETE",
+            LogOperation::Clear => "CLEAR",
+            LogOperation::ModuleStart => "MODULE-START",
+            LogOperation::ConstraintFailed => "CONSTRAINT-FAILED",
+            LogOperation::Other(ref other) => other
+        }
+    }
+
+    /// Whether this records an access which failed.
+    pub fn is_failure(&self) -> bool {
+        matches!(*self, LogOperation::ReadFail | LogOperation::WriteFail | LogOperation::ReplaceFail)
+    }
+}
+
+impl fmt::Display for LogOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// One entry of a `DataBlock`'s access log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+    pub operation: LogOperation,
+    pub section: String,
+    pub name: String,
+    /// The C++ type involved, as CosmoSIS names it. Empty for failed reads and markers.
+    pub type_name: String
+}
+
+impl LogEntry {
+    pub fn succeeded(&self) -> bool {
+        !self.operation.is_failure()
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}/{}", self.operation, self.section, self.name)?;
+        if !self.type_name.is_empty() {
+            write!(f, " ({})", self.type_name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A summary of an access log, for catching dead outputs and misspelled inputs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessReport {
+    /// `(section, name)` pairs which were written or replaced, but never successfully read.
+    pub unread_outputs: Vec<(String, String)>,
+    /// `(section, name)` pairs which some read failed to find, and which were never written.
+    pub failed_reads: Vec<(String, String)>
+}
+
+impl AccessReport {
+    pub fn is_empty(&self) -> bool {
+        self.unread_outputs.is_empty() && self.failed_reads.is_empty()
+    }
+}
+
+impl fmt::Display for AccessReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (section, name) in &self.unread_outputs {
+            writeln!(f, "written but never read: {}/{}", section, name)?;
+        }
+        for (section, name) in &self.failed_reads {
+            writeln!(f, "read but never written: {}/{}", section, name)?;
+        }
+        Ok(())
+    }
+}
+
+impl DataBlock {
+    fn log_entry(&self, i: raw::c_int) -> CosmosisResult<LogEntry> {
+        // One byte more than we tell CosmoSIS about, so that truncated fields stay NUL-terminated.
+        let mut log_type = [0 as raw::c_char; LOG_FIELD_LEN];
+        let mut section = [0 as raw::c_char; LOG_FIELD_LEN];
+        let mut name = [0 as raw::c_char; LOG_FIELD_LEN];
+        let mut type_name = [0 as raw::c_char; LOG_FIELD_LEN];
+        let retval = unsafe {
+            bindings::root::c_datablock_get_log_entry(self.ptr, i, (LOG_FIELD_LEN - 1) as raw::c_int,
+                                                      log_type.as_mut_ptr(), section.as_mut_ptr(),
+                                                      name.as_mut_ptr(), type_name.as_mut_ptr())
+        };
+        let field = |buf: &[raw::c_char]| unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned();
+        wrap_cosmosis_result!(retval,
+                              LogEntry { operation: LogOperation::parse(&field(&log_type)), section: field(&section),
+                                         name: field(&name), type_name: field(&type_name) },
+                              "Could not read access log entry {}", i)
+    }
+
+    /// Every access logged since the block was created, or since the last `clear_log`.
+    pub fn access_log(&self) -> CosmosisResult<Vec<LogEntry>> {
+        self.access_log_since(0)
+    }
+
+    /// How many entries CosmoSIS holds in the access log, counting those before a `clear_log`;
+    /// a position to pass to `access_log_since` later.
+    pub(crate) fn log_len(&self) -> raw::c_int {
+        unsafe { bindings::root::c_datablock_get_log_count(self.ptr) }
+    }
+
+    /// The accesses logged after the first `start` entries, or since the last `clear_log` after
+    /// them, without copying the entries before.
+    pub(crate) fn access_log_since(&self, start: raw::c_int) -> CosmosisResult<Vec<LogEntry>> {
+        let count = self.log_len();
+        let mut entries = Vec::with_capacity((count - start).max(0) as usize);
+        for i in start.max(0)..count {
+            let entry = self.log_entry(i)?;
+            if entry.operation.as_str() == LOG_CLEARED {
+                entries.clear();
+            } else {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Records a custom entry in the access log, e.g. `MODULE-START` with the module's name as
+    /// the section.
+    pub fn log_access(&mut self, operation: &str, section: impl AsKey, name: impl AsKey) -> CosmosisResult<()> {
+        let operation = CString::new(operation).map_err(|_| {
+            CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("Log type contains a NUL byte: {:?}", operation))
+        })?;
+        with_keys(&section, &name, |section, name| {
+            let retval = unsafe {
+                bindings::root::c_datablock_log_access(self.ptr, operation.as_ptr(), section.as_ptr(), name.as_ptr())
+            };
+            wrap_cosmosis_result!(retval, (), "Could not log access to (section, name): ({}, {})",
+                                  section.to_string_lossy(), name.to_string_lossy())
+        })
+    }
+
+    /// Starts a fresh access log, e.g. between modules. The C API cannot erase log entries, so
+    /// this records a marker, and `access_log` only returns entries after the last marker.
+    /// CosmoSIS's own `print_log` still shows everything.
+    pub fn clear_log(&mut self) -> CosmosisResult<()> {
+        self.log_access(LOG_CLEARED, "", "")
+    }
+
+    /// Prints the whole access log to stdout, in CosmoSIS's format.
+    pub fn print_log(&self) -> CosmosisResult<()> {
+        let retval = unsafe { bindings::root::c_datablock_print_log(self.ptr) };
+        wrap_cosmosis_result!(retval, (), "Could not print access log")
+    }
+
+    /// Prints every failed access to stderr, in CosmoSIS's format.
+    pub fn report_failures(&self) -> CosmosisResult<()> {
+        let retval = unsafe { bindings::root::c_datablock_report_failures(self.ptr) };
+        wrap_cosmosis_result!(retval, (), "Could not report access failures")
+    }
+
+    /// Summarizes `access_log`: outputs which nothing read, and failed reads of values which
+    /// nothing wrote. Sections and names are compared case-insensitively, as CosmoSIS does.
+    pub fn access_report(&self) -> CosmosisResult<AccessReport> {
+        let mut written = BTreeSet::new();
+        let mut read = BTreeSet::new();
+        let mut failed = BTreeSet::new();
+        for entry in self.access_log()? {
+            let key = (entry.section.to_lowercase(), entry.name.to_lowercase());
+            match entry.operation {
+                LogOperation::Write | LogOperation::Replace => { written.insert(key); },
+                LogOperation::Read => { read.insert(key); },
+                LogOperation::ReadFail => { failed.insert(key); },
+                _ => ()
+            }
+        }
+        Ok(AccessReport {
+            unread_outputs: written.difference(&read).cloned().collect(),
+            failed_reads: failed.difference(&written).cloned().collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogOperation;
+    use DataBlock;
+
+    #[test]
+    fn test_access_log() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.get::<f64>("cosmological_parameters", "omega_m").unwrap();
+        assert!(db.access_log().unwrap().iter().any(|entry| {
+            entry.operation == LogOperation::Read && entry.section == "cosmological_parameters"
+                && entry.name == "omega_m" && entry.succeeded()
+        }));
+
+        db.clear_log().unwrap();
+        assert!(db.access_log().unwrap().is_empty());
+
+        db.put::<[f64], _>("distances", "z", &[0.0, 1.0][..]).unwrap();
+        db.put("distances", "d_a", 1.0).unwrap();
+        db.get::<Vec<f64>>("distances", "z").unwrap();
+        assert!(db.get::<f64>("cosmological_parameters", "omgea_m").is_err());
+
+        let log = db.access_log().unwrap();
+        assert!(log.iter().all(|entry| entry.section != "cosmological_parameters" || entry.name != "omega_m"));
+        assert_eq!(log.last().map(|entry| &entry.operation), Some(&LogOperation::ReadFail));
+
+        let report = db.access_report().unwrap();
+        assert_eq!(report.unread_outputs, vec![("distances".to_string(), "d_a".to_string())]);
+        assert_eq!(report.failed_reads, vec![("cosmological_parameters".to_string(), "omgea_m".to_string())]);
+        assert_eq!(report.to_string(), "written but never read: distances/d_a\n\
+                                        read but never written: cosmological_parameters/omgea_m\n");
+    }
+}
@@ -0,0 +1,129 @@
+//! Names of the standard CosmoSIS sections, and of common keys within them.
+//!
+//! Using these rather than string literals turns a misspelled section into a compile error
+//! instead of a `DBS_SECTION_NOT_FOUND` at runtime:
+//!
+//! ```
+//! # extern crate cosmosis;
+//! use cosmosis::DataBlock;
+//! use cosmosis::names::{self, cosmological_parameters};
+//! # fn main() {
+//! let mut db = DataBlock::new();
+//! db.put(names::COSMOLOGICAL_PARAMETERS_SECTION, cosmological_parameters::OMEGA_M, 0.3).unwrap();
+//! # }
+//! ```
+//!
+//! The `<NAME>_SECTION` constants are generated at build time from CosmoSIS's
+//! `section_names.txt`, or from the copy vendored with this crate when CosmoSIS does not
+//! provide one, except for the few this crate uses itself, which are always defined. They are
+//! named as in CosmoSIS's C, Fortran and Python bindings.
+
+include!(concat!(env!("OUT_DIR"), "/section_names.rs"));
+
+// Used within this crate, so defined here rather than generated; `BUILTIN_SECTIONS` in build.rs
+// lists them.
+pub const COSMOLOGICAL_PARAMETERS_SECTION: &str = "cosmological_parameters";
+pub const LIKELIHOODS_SECTION: &str = "likelihoods";
+pub const PRIORS_SECTION: &str = "priors";
+
+/// Suffix of the entries in `LIKELIHOODS_SECTION`: a likelihood named `planck` is stored as
+/// `planck_like`.
+pub const LIKELIHOOD_SUFFIX: &str = "_like";
+
+/// The key under which the spectrum or correlation function between tomographic bins `i` and
+/// `j` is stored in sections like `SHEAR_CL_SECTION`, e.g. `bin_2_1`.
+pub fn bin(i: usize, j: usize) -> String {
+    format!("bin_{}_{}", i, j)
+}
+
+pub mod cosmological_parameters {
+    pub const OMEGA_M: &str = "omega_m";
+    pub const OMEGA_B: &str = "omega_b";
+    pub const OMEGA_C: &str = "omega_c";
+    pub const OMEGA_K: &str = "omega_k";
+    pub const OMEGA_NU: &str = "omega_nu";
+    pub const OMEGA_LAMBDA: &str = "omega_lambda";
+    pub const OMBH2: &str = "ombh2";
+    pub const OMCH2: &str = "omch2";
+    pub const OMNUH2: &str = "omnuh2";
+    /// The dimensionless Hubble parameter, `H0 / (100 km/s/Mpc)`.
+    pub const H0: &str = "h0";
+    /// The Hubble parameter in km/s/Mpc.
+    pub const HUBBLE: &str = "hubble";
+    pub const N_S: &str = "n_s";
+    pub const A_S: &str = "a_s";
+    pub const SIGMA_8: &str = "sigma_8";
+    pub const TAU: &str = "tau";
+    pub const W: &str = "w";
+    pub const WA: &str = "wa";
+    pub const MNU: &str = "mnu";
+    pub const NNU: &str = "nnu";
+    pub const YHE: &str = "yhe";
+}
+
+pub mod distances {
+    pub const Z: &str = "z";
+    pub const A: &str = "a";
+    pub const D_A: &str = "d_a";
+    pub const D_M: &str = "d_m";
+    pub const D_L: &str = "d_l";
+    pub const MU: &str = "mu";
+    pub const H: &str = "h";
+    pub const RS_ZDRAG: &str = "rs_zdrag";
+    pub const ZDRAG: &str = "zdrag";
+}
+
+/// Keys shared by the `matter_power_*` sections.
+pub mod matter_power {
+    pub const K_H: &str = "k_h";
+    pub const Z: &str = "z";
+    pub const P_K: &str = "p_k";
+}
+
+pub mod growth_parameters {
+    pub const Z: &str = "z";
+    pub const D_Z: &str = "d_z";
+    pub const F_Z: &str = "f_z";
+}
+
+pub mod cmb_cl {
+    pub const ELL: &str = "ell";
+    pub const TT: &str = "tt";
+    pub const EE: &str = "ee";
+    pub const BB: &str = "bb";
+    pub const TE: &str = "te";
+    pub const PP: &str = "pp";
+}
+
+/// Keys shared by the tomographic `*_cl` and `*_xi` sections; see also `bin`.
+pub mod spectra {
+    pub const ELL: &str = "ell";
+    pub const THETA: &str = "theta";
+    pub const NBIN: &str = "nbin";
+    pub const NBIN_A: &str = "nbin_a";
+    pub const NBIN_B: &str = "nbin_b";
+}
+
+/// Keys of the `wl_number_density` and `nz_*` sections.
+pub mod number_density {
+    pub const Z: &str = "z";
+    pub const NBIN: &str = "nbin";
+    pub const NZ: &str = "nz";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bin, cosmological_parameters, COSMOLOGICAL_PARAMETERS_SECTION, LIKELIHOODS_SECTION};
+    use DataBlock;
+
+    #[test]
+    fn test_standard_names() {
+        assert_eq!(COSMOLOGICAL_PARAMETERS_SECTION, "cosmological_parameters");
+        assert_eq!(LIKELIHOODS_SECTION, "likelihoods");
+        assert_eq!(bin(2, 1), "bin_2_1");
+
+        let mut db = DataBlock::new();
+        db.put(COSMOLOGICAL_PARAMETERS_SECTION, cosmological_parameters::OMEGA_M, 0.3).unwrap();
+        assert_eq!(db.get::<f64>("cosmological_parameters", "omega_m").unwrap(), 0.3);
+    }
+}
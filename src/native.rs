@@ -0,0 +1,201 @@
+use std::ffi::{CStr, CString};
+use std::os::raw;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use libc;
+
+use super::{bindings, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use pipeline::CosmosisModule;
+
+type SetupFn = unsafe extern "C" fn(*mut bindings::root::c_datablock) -> *mut raw::c_void;
+type ExecuteFn = unsafe extern "C" fn(*mut bindings::root::c_datablock, *mut raw::c_void) -> raw::c_int;
+type CleanupFn = unsafe extern "C" fn(*mut raw::c_void) -> raw::c_int;
+
+/// A CosmoSIS module in a shared library, written in C, C++ or Fortran. It must export
+///
+/// * `void * setup(c_datablock * options)`, returning the module's configuration;
+/// * `int execute(c_datablock * block, void * config)`, returning zero on success;
+/// * optionally, `int cleanup(void * config)`.
+///
+/// Fortran compilers append underscores to these names, so `setup_` and `setup__` (and so on)
+/// are looked for too.
+pub struct NativeModule {
+    name: String,
+    handle: *mut raw::c_void,
+    config: *mut raw::c_void,
+    execute: ExecuteFn,
+    cleanup: Option<CleanupFn>
+}
+
+impl NativeModule {
+    /// Opens the library at `path` and runs its `setup` with `options`, usually a block loaded from
+    /// the pipeline's ini file. The module is named after the library's file stem; see
+    /// `with_name`.
+    pub fn load(path: impl AsRef<Path>, options: &DataBlock) -> CosmosisResult<Self> {
+        let path = path.as_ref();
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let error = |reason: String| {
+            CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("Could not load module {} from {}: {}", name, path.display(), reason))
+        };
+
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| error("path contains a NUL byte".to_string()))?;
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(error(last_dl_error()));
+        }
+
+        let symbols = unsafe {
+            (find_symbol(handle, "setup"), find_symbol(handle, "execute"), find_symbol(handle, "cleanup"))
+        };
+        let (setup, execute, cleanup) = match symbols {
+            (Some(setup), Some(execute), cleanup) => unsafe {
+                (std::mem::transmute::<*mut raw::c_void, SetupFn>(setup),
+                 std::mem::transmute::<*mut raw::c_void, ExecuteFn>(execute),
+                 cleanup.map(|cleanup| std::mem::transmute::<*mut raw::c_void, CleanupFn>(cleanup)))
+            },
+            (setup, _, _) => {
+                unsafe { libc::dlclose(handle) };
+                let missing = if setup.is_none() { "setup" } else { "execute" };
+                return Err(error(format!("the library does not export a `{}` function", missing)));
+            }
+        };
+
+        // Setup only reads from the options, but the C API takes a mutable pointer for logging.
+        let config = unsafe { setup(options.ptr) };
+        Ok(NativeModule { name, handle, config, execute, cleanup })
+    }
+
+    /// Renames the module, e.g. after the ini section which configured it.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Runs the module's `cleanup`, reporting a non-zero status as an error. Dropping the module
+    /// does the same, but ignores the status.
+    pub fn cleanup(mut self) -> CosmosisResult<()> {
+        let status = self.run_cleanup();
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("Module {} cleanup returned status {}", self.name, status)))
+        }
+    }
+
+    fn run_cleanup(&mut self) -> raw::c_int {
+        match self.cleanup.take() {
+            Some(cleanup) => unsafe { cleanup(self.config) },
+            None => 0
+        }
+    }
+}
+
+impl CosmosisModule for NativeModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Module status codes are the module's own, not `DATABLOCK_STATUS`es, so any failure is
+    /// reported as `DBS_LOGIC_ERROR` with the status in the reason.
+    fn execute(&mut self, block: &mut DataBlock) -> CosmosisResult<()> {
+        let status = unsafe { (self.execute)(block.ptr, self.config) };
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                .with_reason(format!("execute returned status {}", status)))
+        }
+    }
+}
+
+impl Drop for NativeModule {
+    fn drop(&mut self) {
+        self.run_cleanup();
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+/// Looks up `name`, then the Fortran manglings of it.
+unsafe fn find_symbol(handle: *mut raw::c_void, name: &str) -> Option<*mut raw::c_void> {
+    let candidates = [name.to_string(), format!("{}_", name), format!("{}__", name), name.to_uppercase()];
+    candidates.iter().find_map(|candidate| {
+        let c_name = CString::new(candidate.as_str()).expect("symbol names contain no NUL bytes");
+        let symbol = libc::dlsym(handle, c_name.as_ptr());
+        if symbol.is_null() { None } else { Some(symbol) }
+    })
+}
+
+fn last_dl_error() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NativeModule;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::{env, fs};
+    use {CosmosisModule, DataBlock, DATABLOCK_STATUS};
+
+    const MODULE: &str = "
+        static int calls = 0;
+        void * SETUP(void * options) { return options ? &calls : 0; }
+        int EXECUTE(void * block, void * config) {
+            if (!block || config != &calls) return 1;
+            return ++calls > 2 ? 7 : 0;
+        }
+        int CLEANUP(void * config) { return config == &calls ? 0 : 1; }
+    ";
+
+    /// Compiles `MODULE` with the given symbol names. Panics if there is no working C compiler,
+    /// rather than skipping the test, so that a passing run means the modules really were loaded.
+    fn compile(dir: &Path, stem: &str, suffix: &str) -> PathBuf {
+        let source = dir.join(format!("{}.c", stem));
+        let library = dir.join(format!("{}.so", stem));
+        fs::write(&source, MODULE.replace("SETUP", &format!("setup{}", suffix))
+                                 .replace("EXECUTE", &format!("execute{}", suffix))
+                                 .replace("CLEANUP", &format!("cleanup{}", suffix))).unwrap();
+        let status = Command::new("cc").args(["-shared", "-fPIC", "-o"]).arg(&library).arg(&source).status()
+                                       .expect("NativeModule tests need a C compiler, `cc`, on the PATH");
+        assert!(status.success(), "cc failed to compile {}", source.display());
+        library
+    }
+
+    #[test]
+    fn test_native_module() {
+        let dir = env::temp_dir().join(format!("cosmosis-native-test-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let options = DataBlock::new();
+        let mut block = DataBlock::new();
+
+        let mut module = NativeModule::load(compile(&dir, "c_module", ""), &options).unwrap();
+        assert_eq!(module.name(), "c_module");
+        module.execute(&mut block).unwrap();
+        module.execute(&mut block).unwrap();
+        let err = module.execute(&mut block).unwrap_err();
+        assert!(err.to_string().contains("status 7"), "{}", err);
+        module.cleanup().unwrap();
+
+        let mut module = NativeModule::load(compile(&dir, "fortran_module", "_"), &options).unwrap()
+                                     .with_name("fortran");
+        assert_eq!(module.name(), "fortran");
+        module.execute(&mut block).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_native_module() {
+        let missing = env::temp_dir().join("cosmosis-no-such-module.so");
+        let err = NativeModule::load(missing, &DataBlock::new()).err().unwrap();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(err.to_string().contains("cosmosis-no-such-module"), "{}", err);
+    }
+}
@@ -0,0 +1,181 @@
+use std::ffi::CStr;
+use std::os::raw;
+
+use super::{bindings, Complex, CosmosisDataType, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS,
+            datablock_type_t};
+
+/// A multi-dimensional array, as stored by CosmoSIS's `c_datablock_put_*_array`: the extent of
+/// each dimension, and the elements in row-major (C) order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NdArray<T> {
+    shape: Vec<usize>,
+    data: Vec<T>
+}
+
+impl<T> NdArray<T> {
+    /// Fails with `DBS_EXTENTS_MISMATCH` if `data` does not hold exactly as many elements as
+    /// `shape` describes, and with `DBS_NDIM_NONPOSITIVE` if `shape` is empty.
+    pub fn new(shape: Vec<usize>, data: Vec<T>) -> CosmosisResult<Self> {
+        if shape.is_empty() {
+            return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_NDIM_NONPOSITIVE)
+                       .with_reason("An array needs at least one dimension".to_string()));
+        }
+        if shape.iter().product::<usize>() != data.len() {
+            return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH)
+                       .with_reason(format!("Shape {:?} does not hold {} elements", shape, data.len())));
+        }
+        Ok(NdArray { shape, data })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// The elements in row-major order.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+
+    /// The element at `index`, which has one entry per dimension.
+    pub fn get(&self, index: &[usize]) -> Option<&T> {
+        if index.len() != self.shape.len() || index.iter().zip(&self.shape).any(|(&i, &n)| i >= n) {
+            return None;
+        }
+        let offset = index.iter().zip(&self.shape).fold(0, |offset, (&i, &n)| offset * n + i);
+        self.data.get(offset)
+    }
+}
+
+/// The CosmoSIS type tag of an array with `ndim` dimensions, which is the 2D tag for matrices.
+pub(crate) fn ndarray_type(ndim: usize, two_d: datablock_type_t, n_d: datablock_type_t) -> datablock_type_t {
+    if ndim == 2 { two_d } else { n_d }
+}
+
+fn extents(shape: &[usize]) -> Vec<raw::c_int> {
+    shape.iter().map(|&n| n as raw::c_int).collect()
+}
+
+macro_rules! gen_cosmosis_ndarray_type {
+    ( $rust_name:ty, $cosmo_name:ident, $cosmo_2d_name:ident,
+      $shape_getter:path, $getter:path, $putter:path, $replacer:path ) => {
+        /// The type tag is the ND one, though CosmoSIS reports 2D arrays as e.g. `DBT_DOUBLE2D`;
+        /// both are read.
+        impl CosmosisDataType for NdArray<$rust_name> {
+            type InsertRepr = Self;
+
+            fn cosmosis_type() -> datablock_type_t {
+                datablock_type_t::$cosmo_name
+            }
+
+            fn has_cosmosis_type(ty: datablock_type_t) -> bool {
+                ty == datablock_type_t::$cosmo_name || ty == datablock_type_t::$cosmo_2d_name
+            }
+
+            fn direct_get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
+                let mut ndim: raw::c_int = 0;
+                let retval = unsafe {
+                    bindings::root::c_datablock_get_array_ndim(db.ptr, section.as_ptr(), name.as_ptr(), &mut ndim)
+                };
+                if retval != DATABLOCK_STATUS::DBS_SUCCESS {
+                    return wrap_cosmosis_result!(retval, NdArray { shape: Vec::new(), data: Vec::new() },
+                                                 "Could not get value at (section, name): ({}, {})",
+                                                 section.to_string_lossy(), name.to_string_lossy());
+                }
+                let mut shape: Vec<raw::c_int> = vec![0; ndim.max(0) as usize];
+                let retval = unsafe {
+                    $shape_getter(db.ptr, section.as_ptr(), name.as_ptr(), ndim, shape.as_mut_ptr())
+                };
+                if retval != DATABLOCK_STATUS::DBS_SUCCESS {
+                    return wrap_cosmosis_result!(retval, NdArray { shape: Vec::new(), data: Vec::new() },
+                                                 "Could not get shape at (section, name): ({}, {})",
+                                                 section.to_string_lossy(), name.to_string_lossy());
+                }
+
+                let shape: Vec<usize> = shape.into_iter().map(|n| n.max(0) as usize).collect();
+                let size = shape.iter().product::<usize>();
+                let mut data = Vec::with_capacity(size);
+                let retval = unsafe {
+                    data.set_len(size);
+                    $getter(db.ptr, section.as_ptr(), name.as_ptr(), data.as_mut_ptr(), ndim,
+                            extents(&shape).as_ptr())
+                };
+                wrap_cosmosis_result!(retval, NdArray { shape, data },
+                                      "Could not get value at (section, name): ({}, {})",
+                                      section.to_string_lossy(), name.to_string_lossy())
+            }
+
+            fn direct_put_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
+                let retval = unsafe {
+                    $putter(db.ptr, section.as_ptr(), name.as_ptr(), obj.data.as_ptr(),
+                            obj.ndim() as raw::c_int, extents(&obj.shape).as_ptr())
+                };
+                wrap_cosmosis_result!(retval, (), "Could not put value at (section, name): ({}, {})",
+                                      section.to_string_lossy(), name.to_string_lossy())
+            }
+
+            fn direct_replace_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<Self> {
+                let result = Self::direct_get_datablock(db, section, name)?;
+                Self::direct_overwrite_datablock(db, section, name, obj).map(|()| result)
+            }
+
+            fn direct_overwrite_datablock(db: &mut DataBlock, section: &CStr, name: &CStr, obj: &Self) -> CosmosisResult<()> {
+                let retval = unsafe {
+                    $replacer(db.ptr, section.as_ptr(), name.as_ptr(), obj.data.as_ptr(),
+                              obj.ndim() as raw::c_int, extents(&obj.shape).as_ptr())
+                };
+                wrap_cosmosis_result!(retval, (), "Could not replace value at (section, name): ({}, {})",
+                                      section.to_string_lossy(), name.to_string_lossy())
+            }
+        }
+    }
+}
+
+gen_cosmosis_ndarray_type!(raw::c_int, DBT_INTND, DBT_INT2D,
+                           bindings::root::c_datablock_get_int_array_shape,
+                           bindings::root::c_datablock_get_int_array,
+                           bindings::root::c_datablock_put_int_array,
+                           bindings::root::c_datablock_replace_int_array);
+gen_cosmosis_ndarray_type!(f64, DBT_DOUBLEND, DBT_DOUBLE2D,
+                           bindings::root::c_datablock_get_double_array_shape,
+                           bindings::root::c_datablock_get_double_array,
+                           bindings::root::c_datablock_put_double_array,
+                           bindings::root::c_datablock_replace_double_array);
+gen_cosmosis_ndarray_type!(Complex<f64>, DBT_COMPLEXND, DBT_COMPLEX2D,
+                           bindings::root::c_datablock_get_complex_array_shape,
+                           bindings::root::c_datablock_get_complex_array,
+                           bindings::root::c_datablock_put_complex_array,
+                           bindings::root::c_datablock_replace_complex_array);
+
+#[cfg(test)]
+mod tests {
+    use super::NdArray;
+    use {DataBlock, DATABLOCK_STATUS};
+
+    #[test]
+    fn test_put_get_ndarray() {
+        let matrix = NdArray::new(vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(matrix.get(&[1, 0]), Some(&4.0));
+        assert_eq!(matrix.get(&[0, 3]), None);
+        assert_eq!(NdArray::new(vec![2, 2], vec![1]).unwrap_err().kind, DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH);
+
+        let mut block = DataBlock::new();
+        block.put("theory", "matrix", matrix.clone()).unwrap();
+        assert_eq!(block.get::<NdArray<f64>>("theory", "matrix").unwrap(), matrix);
+        assert!(block.is_type::<NdArray<f64>>("theory", "matrix"));
+        assert!(!block.is_type::<NdArray<i32>>("theory", "matrix"));
+
+        let cube = NdArray::new(vec![2, 1, 2], vec![1, 2, 3, 4]).unwrap();
+        block.put("theory", "cube", cube.clone()).unwrap();
+        assert_eq!(block.get::<NdArray<i32>>("theory", "cube").unwrap(), cube);
+        assert!(block.is_type::<NdArray<i32>>("theory", "cube"));
+        assert!(block.get::<NdArray<f64>>("theory", "cube").is_err());
+    }
+}
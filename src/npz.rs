@@ -0,0 +1,549 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::os::raw;
+use std::path::Path;
+
+use super::{Complex, CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, Value};
+use ndarray::NdArray;
+
+/// Separates the section from the name in member names, e.g. `distances--z.npy`.
+const MEMBER_SEPARATOR: &str = "--";
+const MEMBER_SUFFIX: &str = ".npy";
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+/// `.npy` headers are padded so that the array data starts on a multiple of this many bytes.
+const NPY_ALIGNMENT: usize = 64;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+/// Extra field holding the 64-bit sizes and offset of a zip64 member, as written by `np.savez`.
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+/// Zip format version 2.0: stored members, no zip64.
+const ZIP_VERSION: u16 = 20;
+/// Members are stamped 1980-01-01 00:00, the earliest zip date, so that archives are reproducible.
+const DOS_DATE_1980: u16 = (1 << 5) | 1;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+fn npz_error(message: String) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason(format!("Invalid npz archive: {}", message))
+}
+
+fn member_error(member: &str, message: String) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE)
+        .with_reason(format!("Unsupported npz member {}: {}", member, message))
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn read_bytes(bytes: &[u8], pos: usize, len: usize) -> CosmosisResult<&[u8]> {
+    pos.checked_add(len)
+       .and_then(|end| bytes.get(pos..end))
+       .ok_or_else(|| npz_error(format!("truncated at byte {}", pos)))
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> CosmosisResult<u16> {
+    let b = read_bytes(bytes, pos, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> CosmosisResult<u32> {
+    let b = read_bytes(bytes, pos, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> CosmosisResult<u64> {
+    Ok(u64::from(read_u32(bytes, pos)?) | u64::from(read_u32(bytes, pos + 4)?) << 32)
+}
+
+fn to_usize(n: u64) -> CosmosisResult<usize> {
+    if n > usize::MAX as u64 {
+        Err(npz_error(format!("offset {} does not fit in memory", n)))
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Writes an uncompressed zip archive, as `np.savez` does, one member at a time.
+struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u32,
+    central_directory: Vec<u8>,
+    entries: u16
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn new(writer: W) -> Self {
+        ZipWriter { writer, offset: 0, central_directory: Vec::new(), entries: 0 }
+    }
+
+    fn too_large() -> CosmosisError {
+        npz_error("archives over 4 GiB or with more than 65535 members are not supported".to_string())
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> CosmosisResult<()> {
+        let name = name.as_bytes();
+        let crc = crc32(data);
+        let size = data.len();
+        let (name_len, size) = match (name.len(), size) {
+            (n, s) if n <= u16::MAX as usize && s < u32::MAX as usize => (n as u16, s as u32),
+            _ => return Err(Self::too_large())
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        for &field in &[ZIP_VERSION, 0, 0, 0, DOS_DATE_1980] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[crc, size, size] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[name_len, 0] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        header.extend_from_slice(name);
+
+        let central = &mut self.central_directory;
+        central.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        for &field in &[ZIP_VERSION, ZIP_VERSION, 0, 0, 0, DOS_DATE_1980] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[crc, size, size] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[name_len, 0, 0, 0, 0] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[0, self.offset] {
+            central.extend_from_slice(&field.to_le_bytes());
+        }
+        central.extend_from_slice(name);
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.offset = self.offset.checked_add(header.len() as u32)
+                                 .and_then(|offset| offset.checked_add(size))
+                                 .ok_or_else(Self::too_large)?;
+        self.entries = self.entries.checked_add(1).ok_or_else(Self::too_large)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> CosmosisResult<W> {
+        let size = self.central_directory.len();
+        if size >= u32::MAX as usize || self.offset.checked_add(size as u32).is_none() {
+            return Err(Self::too_large());
+        }
+        let mut end = Vec::with_capacity(END_OF_CENTRAL_DIRECTORY_LEN);
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        for &field in &[0, 0, self.entries, self.entries] {
+            end.extend_from_slice(&field.to_le_bytes());
+        }
+        for &field in &[size as u32, self.offset] {
+            end.extend_from_slice(&field.to_le_bytes());
+        }
+        end.extend_from_slice(&0u16.to_le_bytes());
+
+        self.writer.write_all(&self.central_directory)?;
+        self.writer.write_all(&end)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Locates the central directory, returning its offset and number of entries.
+fn find_central_directory(archive: &[u8]) -> CosmosisResult<(usize, u64)> {
+    let last = archive.len().checked_sub(END_OF_CENTRAL_DIRECTORY_LEN)
+                      .ok_or_else(|| npz_error("too short to be a zip archive".to_string()))?;
+    // The end record is followed by a comment of at most 65535 bytes.
+    let first = last.saturating_sub(u16::MAX as usize);
+    let end = (first..=last).rev()
+                            .find(|&pos| read_u32(archive, pos).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+                            .ok_or_else(|| npz_error("no end of central directory record".to_string()))?;
+
+    let entries = read_u16(archive, end + 10)?;
+    let offset = read_u32(archive, end + 16)?;
+    if entries != u16::MAX && offset != u32::MAX {
+        return Ok((offset as usize, u64::from(entries)));
+    }
+
+    // Too many members or too large an archive for the classic record; use the zip64 one.
+    let locator = end.checked_sub(20).ok_or_else(|| npz_error("missing zip64 locator".to_string()))?;
+    if read_u32(archive, locator)? != ZIP64_LOCATOR_SIGNATURE {
+        return Err(npz_error("missing zip64 locator".to_string()));
+    }
+    let end64 = to_usize(read_u64(archive, locator + 8)?)?;
+    if read_u32(archive, end64)? != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+        return Err(npz_error("bad zip64 end of central directory record".to_string()));
+    }
+    Ok((to_usize(read_u64(archive, end64 + 48)?)?, read_u64(archive, end64 + 32)?))
+}
+
+/// Splits an uncompressed zip archive into its members' names and contents.
+fn read_zip(archive: &[u8]) -> CosmosisResult<Vec<(String, &[u8])>> {
+    let (mut pos, entries) = find_central_directory(archive)?;
+    let mut members = Vec::new();
+    for _ in 0..entries {
+        if read_u32(archive, pos)? != CENTRAL_HEADER_SIGNATURE {
+            return Err(npz_error(format!("bad central directory entry at byte {}", pos)));
+        }
+        let method = read_u16(archive, pos + 10)?;
+        let crc = read_u32(archive, pos + 16)?;
+        let mut size = u64::from(read_u32(archive, pos + 20)?);
+        let name_len = read_u16(archive, pos + 28)? as usize;
+        let extra_len = read_u16(archive, pos + 30)? as usize;
+        let comment_len = read_u16(archive, pos + 32)? as usize;
+        let mut offset = u64::from(read_u32(archive, pos + 42)?);
+        let name = String::from_utf8_lossy(read_bytes(archive, pos + 46, name_len)?).into_owned();
+
+        // The zip64 extra field holds, in order, whichever of the uncompressed size, compressed
+        // size and offset overflowed their 32-bit fields.
+        let uncompressed_overflow = read_u32(archive, pos + 24)? == u32::MAX;
+        let extra = read_bytes(archive, pos + 46 + name_len, extra_len)?;
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let id = read_u16(extra, field)?;
+            let len = read_u16(extra, field + 2)? as usize;
+            if id == ZIP64_EXTRA_ID {
+                let mut value = field + 4;
+                if uncompressed_overflow {
+                    value += 8;
+                }
+                if size == u64::from(u32::MAX) {
+                    size = read_u64(extra, value)?;
+                    value += 8;
+                }
+                if offset == u64::from(u32::MAX) {
+                    offset = read_u64(extra, value)?;
+                }
+            }
+            field += 4 + len;
+        }
+
+        if method != 0 {
+            return Err(member_error(&name, "compressed members (np.savez_compressed) are not supported"
+                                           .to_string()));
+        }
+        let offset = to_usize(offset)?;
+        if read_u32(archive, offset)? != LOCAL_HEADER_SIGNATURE {
+            return Err(npz_error(format!("bad local header for {}", name)));
+        }
+        let data_start = offset + 30 + read_u16(archive, offset + 26)? as usize + read_u16(archive, offset + 28)? as usize;
+        let data = read_bytes(archive, data_start, to_usize(size)?)?;
+        if crc32(data) != crc {
+            return Err(npz_error(format!("checksum mismatch for {}", name)));
+        }
+        members.push((name, data));
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+/// Encodes `value` as a version 1.0 `.npy` file. Scalars are 0-dimensional arrays.
+fn encode_npy(value: &Value) -> Vec<u8> {
+    fn le_bytes<T, F, B>(values: &[T], to_bytes: F) -> Vec<u8>
+        where F: Fn(&T) -> B, B: AsRef<[u8]> {
+        values.iter().flat_map(|v| to_bytes(v).as_ref().to_vec()).collect()
+    }
+    let complex_bytes = |z: &Complex<f64>| {
+        let mut bytes = z.re.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&z.im.to_le_bytes());
+        bytes
+    };
+
+    let (descr, data) = match *value {
+        Value::Int(n) => ("<i4".to_string(), n.to_le_bytes().to_vec()),
+        Value::Bool(b) => ("|b1".to_string(), vec![b as u8]),
+        Value::Double(x) => ("<f8".to_string(), x.to_le_bytes().to_vec()),
+        Value::Complex(ref z) => ("<c16".to_string(), complex_bytes(z)),
+        Value::String(ref s) => {
+            // numpy has no zero-width unicode dtype for a saved scalar; '' is stored as <U1.
+            let width = s.chars().count().max(1);
+            let mut data = le_bytes(&s.chars().collect::<Vec<_>>(), |&c| (c as u32).to_le_bytes());
+            data.resize(4 * width, 0);
+            (format!("<U{}", width), data)
+        },
+        Value::IntArray(ref v) => ("<i4".to_string(), le_bytes(v, |n| n.to_le_bytes())),
+        Value::DoubleArray(ref v) => ("<f8".to_string(), le_bytes(v, |x| x.to_le_bytes())),
+        Value::ComplexArray(ref v) => ("<c16".to_string(), le_bytes(v, complex_bytes)),
+        Value::IntNdArray(ref a) => ("<i4".to_string(), le_bytes(a.data(), |n| n.to_le_bytes())),
+        Value::DoubleNdArray(ref a) => ("<f8".to_string(), le_bytes(a.data(), |x| x.to_le_bytes())),
+        Value::ComplexNdArray(ref a) => ("<c16".to_string(), le_bytes(a.data(), complex_bytes)),
+    };
+    // Python's tuple syntax, which needs a trailing comma for one element.
+    let shape = match value.shape() {
+        None => "()".to_string(),
+        Some(ref shape) if shape.len() == 1 => format!("({},)", shape[0]),
+        Some(shape) => format!("({})", shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", "))
+    };
+
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((NPY_ALIGNMENT - unpadded % NPY_ALIGNMENT) % NPY_ALIGNMENT));
+    header.push('\n');
+
+    let mut npy = NPY_MAGIC.to_vec();
+    npy.extend_from_slice(&[1, 0]);
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    npy.extend_from_slice(&data);
+    npy
+}
+
+/// The text following `'key':` in an `.npy` header dictionary.
+fn header_field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("'{}':", key);
+    header.find(&pattern).map(|start| header[start + pattern.len()..].trim_start())
+}
+
+/// Reads the dtype, shape and data of an `.npy` file.
+fn parse_npy<'a>(member: &str, npy: &'a [u8]) -> CosmosisResult<(String, Vec<usize>, &'a [u8])> {
+    let invalid = |message: &str| npz_error(format!("{}: {}", member, message));
+    if read_bytes(npy, 0, NPY_MAGIC.len()).ok() != Some(NPY_MAGIC) {
+        return Err(invalid("not an .npy file"));
+    }
+    let (header_len, header_start) = match read_bytes(npy, 6, 1)?[0] {
+        1 => (read_u16(npy, 8)? as usize, 10),
+        2 | 3 => (to_usize(u64::from(read_u32(npy, 8)?))?, 12),
+        major => return Err(invalid(&format!("unsupported .npy version {}", major)))
+    };
+    let header = ::std::str::from_utf8(read_bytes(npy, header_start, header_len)?)
+                     .map_err(|_| invalid("header is not valid text"))?;
+
+    let descr = header_field(header, "descr")
+                    .and_then(|rest| rest.get(1..))
+                    .and_then(|rest| rest.find('\'').map(|end| rest[..end].to_string()))
+                    .ok_or_else(|| invalid("header has no 'descr'"))?;
+    let shape = header_field(header, "shape")
+                    .and_then(|rest| rest.strip_prefix('('))
+                    .and_then(|rest| rest.find(')').map(|end| &rest[..end]))
+                    .ok_or_else(|| invalid("header has no 'shape'"))?;
+    let shape = shape.split(',')
+                     .map(|dim| dim.trim())
+                     .filter(|dim| !dim.is_empty())
+                     .map(|dim| dim.parse::<usize>().map_err(|_| invalid(&format!("bad dimension {:?}", dim))))
+                     .collect::<CosmosisResult<Vec<_>>>()?;
+    let fortran_order = header_field(header, "fortran_order").is_some_and(|rest| rest.starts_with("True"));
+    if fortran_order && shape.len() > 1 {
+        return Err(member_error(member, "Fortran-ordered arrays are not supported".to_string()));
+    }
+    Ok((descr, shape, &npy[header_start + header_len..]))
+}
+
+/// Decodes an `.npy` file as the `Value` it would have been written from.
+fn decode_npy(member: &str, npy: &[u8]) -> CosmosisResult<Value> {
+    let (descr, shape, data) = parse_npy(member, npy)?;
+    let len = match shape.len() {
+        0 => None,
+        _ => Some(shape.iter().try_fold(1usize, |len, &n| len.checked_mul(n))
+                       .ok_or_else(|| member_error(member, format!("shape {:?} is too large", shape)))?)
+    };
+    let count = len.unwrap_or(1);
+    if descr.len() < 2 || !descr.is_char_boundary(1) {
+        return Err(member_error(member, format!("dtype {:?}", descr)));
+    }
+    let (order, kind) = descr.split_at(1);
+    if order == ">" {
+        return Err(member_error(member, format!("big-endian dtype {}", descr)));
+    }
+
+    let item_size = match kind {
+        "b1" => 1,
+        "i4" => 4,
+        "i8" | "f8" => 8,
+        "c16" => 16,
+        _ if kind.starts_with('U') => 4 * kind[1..].parse::<usize>()
+                                                   .map_err(|_| member_error(member, format!("dtype {}", descr)))?,
+        _ => return Err(member_error(member, format!("dtype {} (expected int32, float64, complex128, bool or \
+                                                      unicode)", descr)))
+    };
+    let data = count.checked_mul(item_size)
+                    .and_then(|size| data.get(..size))
+                    .ok_or_else(|| npz_error(format!("{}: truncated array data", member)))?;
+    let items = data.chunks(item_size.max(1));
+    let f64_at = |b: &[u8], i: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&b[i..i + 8]);
+        f64::from_le_bytes(bytes)
+    };
+    let i32_at = |b: &[u8]| raw::c_int::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let complex_at = |b: &[u8]| Complex { re: f64_at(b, 0), im: f64_at(b, 8) };
+    let narrow = |b: &[u8]| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(b);
+        let n = i64::from_le_bytes(bytes);
+        if n as raw::c_int as i64 == n {
+            Ok(n as raw::c_int)
+        } else {
+            Err(member_error(member, format!("int64 value {} does not fit in an int32", n)))
+        }
+    };
+
+    if shape.len() > 1 {
+        return Ok(match kind {
+            "i4" => Value::IntNdArray(NdArray::new(shape, items.map(i32_at).collect())?),
+            "i8" => Value::IntNdArray(NdArray::new(shape, items.map(narrow).collect::<CosmosisResult<_>>()?)?),
+            "f8" => Value::DoubleNdArray(NdArray::new(shape, items.map(|b| f64_at(b, 0)).collect())?),
+            "c16" => Value::ComplexNdArray(NdArray::new(shape, items.map(complex_at).collect())?),
+            _ => return Err(member_error(member, format!("arrays of dtype {} cannot be stored in a DataBlock",
+                                                         descr)))
+        });
+    }
+
+    Ok(match (kind, len) {
+        ("b1", None) => Value::Bool(data[0] != 0),
+        ("i4", None) => Value::Int(i32_at(data)),
+        ("i8", None) => Value::Int(narrow(data)?),
+        ("f8", None) => Value::Double(f64_at(data, 0)),
+        ("c16", None) => Value::Complex(complex_at(data)),
+        ("i4", Some(_)) => Value::IntArray(items.map(i32_at).collect()),
+        ("i8", Some(_)) => Value::IntArray(items.map(narrow).collect::<CosmosisResult<_>>()?),
+        ("f8", Some(_)) => Value::DoubleArray(items.map(|b| f64_at(b, 0)).collect()),
+        ("c16", Some(_)) => Value::ComplexArray(items.map(complex_at).collect()),
+        (_, None) => {
+            // Unicode scalars are UTF-32, padded with trailing NULs.
+            let chars = data.chunks(4)
+                            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                            .take_while(|&c| c != 0)
+                            .map(|c| ::std::char::from_u32(c)
+                                         .ok_or_else(|| npz_error(format!("{}: invalid character", member))))
+                            .collect::<CosmosisResult<String>>()?;
+            Value::String(chars)
+        },
+        (_, Some(_)) => return Err(member_error(member, format!("arrays of dtype {} cannot be stored in a \
+                                                                 DataBlock", descr)))
+    })
+}
+
+impl DataBlock {
+    /// Writes this block as an uncompressed `.npz` archive readable by `numpy.load`. Each value
+    /// becomes a member named `section--name.npy`: scalars are 0-dimensional arrays, arrays keep
+    /// their shape (in C order), and ints,
+    /// doubles, complex numbers, bools and strings have dtypes `int32`, `float64`, `complex128`,
+    /// `bool` and unicode respectively.
+    pub fn write_npz(&self, writer: impl Write) -> CosmosisResult<()> {
+        let mut zip = ZipWriter::new(writer);
+        let mut sections = self.sections();
+        sections.sort();
+        for section in sections {
+            let mut names = self.names(&section)?;
+            names.sort();
+            for name in names {
+                let value = self.get_value(&section, &name)?;
+                let member = format!("{}{}{}{}", section, MEMBER_SEPARATOR, name, MEMBER_SUFFIX);
+                zip.add(&member, &encode_npy(&value))?;
+            }
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Reads a block from an `.npz` archive laid out as by `write_npz`. `int64` arrays, as
+    /// numpy creates by default, are accepted if every element fits in an `int32`. Compressed
+    /// archives are rejected, since this crate cannot inflate them, as are Fortran-ordered
+    /// multi-dimensional arrays.
+    pub fn read_npz(mut reader: impl Read) -> CosmosisResult<Self> {
+        let mut archive = Vec::new();
+        reader.read_to_end(&mut archive)?;
+
+        let mut db = DataBlock::new();
+        for (member, npy) in read_zip(&archive)? {
+            let key = member.strip_suffix(MEMBER_SUFFIX).unwrap_or(&member);
+            let split = key.find(MEMBER_SEPARATOR).ok_or_else(|| {
+                member_error(&member, format!("name is not of the form section{}name{}", MEMBER_SEPARATOR,
+                                              MEMBER_SUFFIX))
+            })?;
+            let (section, name) = (&key[..split], &key[split + MEMBER_SEPARATOR.len()..]);
+            db.put_value(section, name, &decode_npy(&member, npy)?)?;
+        }
+        Ok(db)
+    }
+
+    /// Writes this block to the `.npz` file at `path`; see `write_npz`.
+    pub fn save_npz(&self, path: impl AsRef<Path>) -> CosmosisResult<()> {
+        self.write_npz(BufWriter::new(File::create(path)?))
+    }
+
+    /// Reads a block from the `.npz` file at `path`; see `read_npz`.
+    pub fn load_npz(path: impl AsRef<Path>) -> CosmosisResult<Self> {
+        DataBlock::read_npz(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_npy, ZipWriter};
+    use std::f64;
+    use std::os::raw;
+    use {Complex, DataBlock, DATABLOCK_STATUS, NdArray, Tolerance, Value};
+
+    #[test]
+    fn test_npz_round_trip() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "n_massive", 3 as raw::c_int).unwrap();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put("cosmological_parameters", "flat", true).unwrap();
+        db.put("cosmological_parameters", "z_c", Complex { re: 1.0, im: f64::NAN }).unwrap();
+        db.put::<str, _>("cosmological_parameters", "label", "Ωm–σ8").unwrap();
+        db.put::<str, _>("cosmological_parameters", "empty", "").unwrap();
+        db.put::<[raw::c_int], _>("distances", "bins", &[1, -2, 3][..]).unwrap();
+        db.put::<[f64], _>("distances", "z", &[0.0, 0.5, f64::INFINITY][..]).unwrap();
+        db.put::<[f64], _>("distances", "none", &[][..]).unwrap();
+        db.put::<[Complex<f64>], _>("distances", "phase", &[Complex { re: 0.0, im: 1.0 }][..]).unwrap();
+        db.put("matrices", "counts", NdArray::new(vec![2, 3], vec![1, 2, 3, 4, 5, 6]).unwrap()).unwrap();
+        db.put("matrices", "cov", NdArray::new(vec![2, 2], vec![1.0, 0.5, 0.5, f64::NAN]).unwrap()).unwrap();
+        db.put("matrices", "modes", NdArray::new(vec![1, 2], vec![Complex { re: 1.0, im: -1.0 },
+                                                                 Complex { re: 0.0, im: 2.0 }]).unwrap()).unwrap();
+
+        let mut npz = Vec::new();
+        db.write_npz(&mut npz).unwrap();
+        let loaded = DataBlock::read_npz(&npz[..]).unwrap();
+        assert_eq!(loaded.get::<NdArray<raw::c_int>>("matrices", "counts").unwrap().shape(), &[2, 3]);
+        assert_eq!(loaded.get::<String>("cosmological_parameters", "label").unwrap(), "Ωm–σ8");
+        assert_blocks_close!(db, loaded, Tolerance::exact());
+    }
+
+    #[test]
+    fn test_npy_header() {
+        let npy = encode_npy(&Value::DoubleArray(vec![1.0, 2.0]));
+        assert_eq!(npy.len(), 128 + 16);
+        assert_eq!(&npy[..10], b"\x93NUMPY\x01\x00\x76\x00");
+        assert!(npy[10..].starts_with(b"{'descr': '<f8', 'fortran_order': False, 'shape': (2,), }"));
+        assert_eq!(npy[127], b'\n');
+
+        let matrix = encode_npy(&Value::IntNdArray(NdArray::new(vec![2, 3], vec![0; 6]).unwrap()));
+        assert!(matrix[10..].starts_with(b"{'descr': '<i4', 'fortran_order': False, 'shape': (2, 3), }"));
+    }
+
+    #[test]
+    fn test_npz_errors() {
+        let mut db = DataBlock::new();
+        db.put::<[f64], _>("distances", "z", &[0.0, 0.5][..]).unwrap();
+        let mut npz = Vec::new();
+        db.write_npz(&mut npz).unwrap();
+        for len in 0..npz.len() {
+            assert!(DataBlock::read_npz(&npz[..len]).is_err());
+        }
+        let mut corrupt = npz.clone();
+        corrupt[120] ^= 0xff;
+        assert!(DataBlock::read_npz(&corrupt[..]).is_err());
+
+        let mut matrix = encode_npy(&Value::DoubleNdArray(NdArray::new(vec![2, 2], vec![0.0; 4]).unwrap()));
+        let order = matrix.windows(6).position(|w| w == b"False,").unwrap();
+        matrix[order..order + 6].copy_from_slice(b"True, ");
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("distances--matrix.npy", &matrix).unwrap();
+        let npz = zip.finish().unwrap();
+        assert_eq!(DataBlock::read_npz(&npz[..]).unwrap_err().kind, DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+    }
+}
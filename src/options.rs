@@ -0,0 +1,324 @@
+use std::fmt;
+use std::ops::RangeBounds;
+use std::os::raw;
+use std::path::{Path, PathBuf};
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS, Value};
+
+/// The section of the setup block holding the options from a module's own ini section.
+pub const OPTION_SECTION: &str = "module_options";
+
+/// Types which may be read from a module option. Options arrive from the ini file either as
+/// strings or, where CosmoSIS recognised them, as numbers and bools; every implementation
+/// accepts both.
+pub trait OptionValue: Sized {
+    /// What the option should have been, for error messages, e.g. `an integer`.
+    fn expected() -> &'static str;
+
+    /// Parses one value written in the ini file.
+    fn from_option_str(s: &str) -> Option<Self>;
+
+    /// Converts a value CosmoSIS has already typed.
+    fn from_option_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::String(ref s) => Self::from_option_str(s.trim()),
+            _ => None
+        }
+    }
+}
+
+impl OptionValue for f64 {
+    fn expected() -> &'static str {
+        "a number"
+    }
+
+    fn from_option_str(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+
+    fn from_option_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Double(x) => Some(x),
+            Value::Int(n) => Some(f64::from(n)),
+            Value::String(ref s) => Self::from_option_str(s.trim()),
+            _ => None
+        }
+    }
+}
+
+macro_rules! gen_integer_option {
+    ( $rust_name:ty ) => {
+        impl OptionValue for $rust_name {
+            fn expected() -> &'static str {
+                "an integer"
+            }
+
+            fn from_option_str(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+
+            fn from_option_value(value: &Value) -> Option<Self> {
+                match *value {
+                    Value::Int(n) => Self::from_option_str(&n.to_string()),
+                    Value::String(ref s) => Self::from_option_str(s.trim()),
+                    _ => None
+                }
+            }
+        }
+    }
+}
+
+gen_integer_option!(raw::c_int);
+gen_integer_option!(i64);
+gen_integer_option!(usize);
+
+impl OptionValue for bool {
+    fn expected() -> &'static str {
+        "a boolean (T/F, true/false, yes/no or 1/0)"
+    }
+
+    fn from_option_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "1" => Some(true),
+            "f" | "false" | "n" | "no" | "0" => Some(false),
+            _ => None
+        }
+    }
+
+    fn from_option_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::Bool(b) => Some(b),
+            Value::Int(0) => Some(false),
+            Value::Int(1) => Some(true),
+            Value::String(ref s) => Self::from_option_str(s.trim()),
+            _ => None
+        }
+    }
+}
+
+impl OptionValue for String {
+    fn expected() -> &'static str {
+        "a string"
+    }
+
+    fn from_option_str(s: &str) -> Option<Self> {
+        Some(s.to_string())
+    }
+
+    /// Numbers are returned as written, since an option like `name = 1` is still a name.
+    fn from_option_value(value: &Value) -> Option<Self> {
+        match *value {
+            Value::String(ref s) => Some(s.trim().to_string()),
+            Value::Int(n) => Some(n.to_string()),
+            Value::Double(x) => Some(x.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None
+        }
+    }
+}
+
+/// Typed access to a module's options, as passed to its `setup`:
+///
+/// ```
+/// # extern crate cosmosis;
+/// # use cosmosis::{DataBlock, Options};
+/// # fn main() {
+/// # let mut block = DataBlock::new();
+/// # block.put::<str, _>("module_options", "zmax", "3.0").unwrap();
+/// # block.put::<str, _>("module_options", "bins", "1, 2, 4").unwrap();
+/// let options = Options::new(&block);
+/// let zmax: f64 = options.get_bounded("zmax", 0.0..10.0).unwrap();
+/// let nz: usize = options.get_or("nz", 100).unwrap();
+/// let bins: Vec<usize> = options.get_list("bins").unwrap();
+/// assert_eq!((zmax, nz, bins), (3.0, 100, vec![1, 2, 4]));
+/// # }
+/// ```
+///
+/// Every error names the section and key which caused it.
+pub struct Options<'a> {
+    block: &'a DataBlock,
+    section: String,
+    base_dir: Option<PathBuf>
+}
+
+impl<'a> Options<'a> {
+    /// Reads the module's own options, from `OPTION_SECTION`.
+    pub fn new(block: &'a DataBlock) -> Self {
+        Options::for_section(block, OPTION_SECTION)
+    }
+
+    /// Reads options from any section of the setup block, e.g. another ini section.
+    pub fn for_section(block: &'a DataBlock, section: &str) -> Self {
+        Options { block, section: section.to_string(), base_dir: None }
+    }
+
+    /// Resolves relative paths from `get_path` against `dir`, usually the directory containing
+    /// the ini file. By default they are left relative to the working directory, as CosmoSIS does.
+    pub fn relative_to(self, dir: impl Into<PathBuf>) -> Self {
+        Options { base_dir: Some(dir.into()), ..self }
+    }
+
+    pub fn section(&self) -> &str {
+        &self.section
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.block.contains(&self.section, key)
+    }
+
+    fn error(&self, kind: DATABLOCK_STATUS, key: &str, message: impl fmt::Display) -> CosmosisError {
+        CosmosisError::new(kind).with_reason(format!("Option [{}] {}: {}", self.section, key, message))
+    }
+
+    fn value(&self, key: &str) -> CosmosisResult<Option<Value>> {
+        if !self.contains(key) {
+            return Ok(None);
+        }
+        self.block.get_value(&self.section, key).map(Some)
+                  .map_err(|err| self.error(err.kind, key, err))
+    }
+
+    fn convert<T: OptionValue>(&self, key: &str, value: &Value) -> CosmosisResult<T> {
+        T::from_option_value(value).ok_or_else(|| {
+            self.error(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE, key,
+                       format!("expected {}, found {}", T::expected(), value))
+        })
+    }
+
+    /// A required option.
+    pub fn get<T: OptionValue>(&self, key: &str) -> CosmosisResult<T> {
+        self.get_optional(key)?
+            .ok_or_else(|| self.error(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND, key, "required option is missing"))
+    }
+
+    /// An option which may be left out of the ini file.
+    pub fn get_optional<T: OptionValue>(&self, key: &str) -> CosmosisResult<Option<T>> {
+        match self.value(key)? {
+            Some(value) => self.convert(key, &value).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// An option which takes `default` if left out of the ini file. A value which is present but
+    /// malformed is still an error.
+    pub fn get_or<T: OptionValue>(&self, key: &str, default: T) -> CosmosisResult<T> {
+        self.get_optional(key).map(|value| value.unwrap_or(default))
+    }
+
+    /// A required option which must lie within `bounds`, e.g. `0.0..1.0` or `1..`.
+    pub fn get_bounded<T, R>(&self, key: &str, bounds: R) -> CosmosisResult<T>
+        where T: OptionValue + PartialOrd + fmt::Debug,
+              R: RangeBounds<T> + fmt::Debug {
+        let value = self.get(key)?;
+        if bounds.contains(&value) {
+            Ok(value)
+        } else {
+            Err(self.error(DATABLOCK_STATUS::DBS_LOGIC_ERROR, key,
+                           format!("{:?} is outside the allowed range {:?}", value, bounds)))
+        }
+    }
+
+    /// A required list, written in the ini file as values separated by whitespace and/or commas.
+    /// Arrays and single values which CosmoSIS has already typed are accepted too.
+    pub fn get_list<T: OptionValue>(&self, key: &str) -> CosmosisResult<Vec<T>> {
+        let value = self.value(key)?
+                        .ok_or_else(|| self.error(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND, key,
+                                                  "required option is missing"))?;
+        let elements = match value {
+            Value::String(ref s) => {
+                return s.split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|element| !element.is_empty())
+                        .map(|element| T::from_option_str(element).ok_or_else(|| {
+                            self.error(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE, key,
+                                       format!("expected a list of {}, found {:?}", T::expected(), element))
+                        }))
+                        .collect();
+            },
+            Value::IntArray(ref v) => v.iter().map(|&n| Value::Int(n)).collect(),
+            Value::DoubleArray(ref v) => v.iter().map(|&x| Value::Double(x)).collect(),
+            other => vec![other]
+        };
+        elements.iter().map(|element| self.convert(key, element)).collect()
+    }
+
+    /// A required file path, resolved as described in `relative_to`.
+    pub fn get_path(&self, key: &str) -> CosmosisResult<PathBuf> {
+        let path = PathBuf::from(self.get::<String>(key)?);
+        Ok(match self.base_dir {
+            Some(ref dir) if path.is_relative() => Path::new(dir).join(path),
+            _ => path
+        })
+    }
+
+    /// A required option which must be one of a fixed set of strings, each standing for a value
+    /// of `T`. Matching is case-insensitive.
+    pub fn get_choice<T: Clone>(&self, key: &str, choices: &[(&str, T)]) -> CosmosisResult<T> {
+        let chosen = self.get::<String>(key)?;
+        choices.iter()
+               .find(|&&(name, _)| name.eq_ignore_ascii_case(&chosen))
+               .map(|(_, value)| value.clone())
+               .ok_or_else(|| {
+                   let names: Vec<_> = choices.iter().map(|&(name, _)| name).collect();
+                   self.error(DATABLOCK_STATUS::DBS_LOGIC_ERROR, key,
+                              format!("{:?} is not one of {}", chosen, names.join(", ")))
+               })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use std::os::raw;
+    use std::path::PathBuf;
+    use {DataBlock, DATABLOCK_STATUS};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Method { Linear, Cubic }
+
+    #[test]
+    fn test_options() {
+        let mut block = DataBlock::new();
+        block.put::<str, _>("module_options", "zmax", " 3.5 ").unwrap();
+        block.put("module_options", "nz", 50 as raw::c_int).unwrap();
+        block.put::<str, _>("module_options", "verbose", "T").unwrap();
+        block.put::<str, _>("module_options", "bins", "1, 2 4,8").unwrap();
+        block.put::<[f64], _>("module_options", "weights", &[0.5, 1.0][..]).unwrap();
+        block.put::<str, _>("module_options", "data_file", "data/cl.txt").unwrap();
+        block.put::<str, _>("module_options", "method", "Cubic").unwrap();
+
+        let options = Options::new(&block).relative_to("/ini");
+        assert_eq!(options.get::<f64>("zmax").unwrap(), 3.5);
+        assert_eq!(options.get::<f64>("nz").unwrap(), 50.0);
+        assert_eq!(options.get::<String>("nz").unwrap(), "50");
+        assert!(options.get::<bool>("verbose").unwrap());
+        assert_eq!(options.get_or("missing", 7usize).unwrap(), 7);
+        assert_eq!(options.get_optional::<f64>("missing").unwrap(), None);
+        assert_eq!(options.get_list::<usize>("bins").unwrap(), vec![1, 2, 4, 8]);
+        assert_eq!(options.get_list::<f64>("weights").unwrap(), vec![0.5, 1.0]);
+        assert_eq!(options.get_path("data_file").unwrap(), PathBuf::from("/ini/data/cl.txt"));
+        assert_eq!(options.get_choice("method", &[("linear", Method::Linear), ("cubic", Method::Cubic)]).unwrap(),
+                   Method::Cubic);
+        assert_eq!(options.get_bounded::<raw::c_int, _>("nz", 1..=100).unwrap(), 50);
+    }
+
+    #[test]
+    fn test_option_errors() {
+        let mut block = DataBlock::new();
+        block.put::<str, _>("camb", "zmax", "lots").unwrap();
+        block.put::<str, _>("camb", "bins", "1 two").unwrap();
+        let options = Options::for_section(&block, "camb");
+
+        let err = options.get::<f64>("zmax").unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+        assert!(err.to_string().contains("[camb] zmax"), "{}", err);
+        // A malformed value is an error even when there is a default.
+        assert!(options.get_or("zmax", 1.0).is_err());
+
+        let err = options.get::<f64>("nz").unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_NAME_NOT_FOUND);
+        assert!(err.to_string().contains("[camb] nz"), "{}", err);
+
+        assert!(options.get_list::<usize>("bins").unwrap_err().to_string().contains("\"two\""));
+        assert!(options.get_bounded::<f64, _>("zmax", 0.0..1.0).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use expression::{Expression, Reference};
+use ini::{Ini, IniError, IniLocation};
+
+/// Parameters are identified case-insensitively, as in the `DataBlock`.
+fn parameter_key(section: &str, name: &str) -> (String, String) {
+    (section.to_lowercase(), name.to_lowercase())
+}
+
+/// One parameter from a values file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub section: String,
+    pub name: String,
+    /// The starting point of a varied parameter, or the value of a fixed one.
+    pub start: f64,
+    /// `(min, max)` for a varied parameter; `None` for a fixed one.
+    pub bounds: Option<(f64, f64)>
+}
+
+impl Parameter {
+    pub fn is_varied(&self) -> bool {
+        self.bounds.is_some()
+    }
+
+    /// Whether `x` lies within the parameter's bounds, inclusive. A fixed parameter only
+    /// contains its own value.
+    pub fn contains(&self, x: f64) -> bool {
+        match self.bounds {
+            Some((min, max)) => min <= x && x <= max,
+            None => x == self.start
+        }
+    }
+
+    /// Parses one `name = value`, `name = min max` or `name = min start max` entry. Two numbers
+    /// start the parameter halfway between its bounds.
+    fn parse(section: &str, name: &str, value: &str, location: Option<&IniLocation>) -> Result<Self, IniError> {
+        let error = |message: String| IniError { location: location.cloned(), message };
+        let numbers = value.split_whitespace()
+                           .map(|word| word.parse::<f64>().map_err(|_| {
+                               error(format!("{}--{}: {:?} is not a number", section, name, word))
+                           }))
+                           .collect::<Result<Vec<_>, _>>()?;
+        let (start, bounds) = match numbers[..] {
+            [value] => (value, None),
+            [min, max] => (0.5 * (min + max), Some((min, max))),
+            [min, start, max] => (start, Some((min, max))),
+            _ => return Err(error(format!("{}--{}: expected `value`, `min max` or `min start max`, found {:?}",
+                                          section, name, value)))
+        };
+        let parameter = Parameter { section: section.to_string(), name: name.to_string(), start, bounds };
+        if let Some((min, max)) = bounds {
+            if min > max || !parameter.contains(start) {
+                return Err(error(format!("{}--{}: need min <= start <= max, found {:?}", section, name, value)));
+            }
+        }
+        Ok(parameter)
+    }
+}
+
+/// Written as CosmoSIS names parameters in its output, e.g. `cosmological_parameters--omega_m`.
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}--{}", self.section, self.name)
+    }
+}
+
+/// A parameter defined by an `Expression` of other parameters, e.g. `omega_c = omega_m - omega_b`.
+/// Bare names in the expression refer to parameters in the same section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedParameter {
+    pub section: String,
+    pub name: String,
+    pub expression: Expression,
+    location: Option<IniLocation>
+}
+
+impl DerivedParameter {
+    fn resolve(&self, reference: &Reference) -> (String, String) {
+        parameter_key(reference.section.as_ref().unwrap_or(&self.section), &reference.name)
+    }
+
+    fn error(&self, message: String) -> IniError {
+        IniError { location: self.location.clone(), message: format!("{}: {}", self, message) }
+    }
+}
+
+impl fmt::Display for DerivedParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}--{}", self.section, self.name)
+    }
+}
+
+/// Sorts `derived` so that each parameter comes after those its expression uses, checking that
+/// every reference is to a known parameter and that no definition depends on itself.
+fn order_derived(parameters: &[Parameter], derived: Vec<DerivedParameter>) -> Result<Vec<DerivedParameter>, IniError> {
+    struct Sorter<'a> {
+        known: HashSet<(String, String)>,
+        index: HashMap<(String, String), usize>,
+        derived: &'a [DerivedParameter],
+        /// 0 for unvisited, 1 while visiting dependencies, 2 once placed in `order`.
+        state: Vec<u8>,
+        path: Vec<usize>,
+        order: Vec<usize>
+    }
+
+    impl<'a> Sorter<'a> {
+        fn visit(&mut self, i: usize) -> Result<(), IniError> {
+            self.state[i] = 1;
+            self.path.push(i);
+            let parameter = &self.derived[i];
+            for reference in parameter.expression.references() {
+                let key = parameter.resolve(reference);
+                match self.index.get(&key).cloned() {
+                    Some(j) if self.state[j] == 0 => self.visit(j)?,
+                    Some(j) if self.state[j] == 1 => {
+                        let start = self.path.iter().position(|&k| k == j).unwrap();
+                        let cycle: Vec<_> = self.path[start..].iter().chain(Some(&j))
+                                                .map(|&k| self.derived[k].to_string()).collect();
+                        return Err(self.derived[j].error(format!("cyclic definition {}", cycle.join(" -> "))));
+                    },
+                    Some(_) => (),
+                    None if self.known.contains(&key) => (),
+                    None => return Err(parameter.error(format!("unknown parameter {}", reference)))
+                }
+            }
+            self.path.pop();
+            self.state[i] = 2;
+            self.order.push(i);
+            Ok(())
+        }
+    }
+
+    let mut sorter = Sorter {
+        known: parameters.iter().map(|p| parameter_key(&p.section, &p.name)).collect(),
+        index: derived.iter().enumerate().map(|(i, d)| (parameter_key(&d.section, &d.name), i)).collect(),
+        derived: &derived,
+        state: vec![0; derived.len()],
+        path: Vec::new(),
+        order: Vec::new()
+    };
+    for i in 0..derived.len() {
+        if sorter.state[i] == 0 {
+            sorter.visit(i)?;
+        }
+    }
+    let order = sorter.order;
+    let mut derived: Vec<_> = derived.into_iter().map(Some).collect();
+    Ok(order.into_iter().map(|i| derived[i].take().unwrap()).collect())
+}
+
+/// The parameters defined by a CosmoSIS values file:
+///
+/// ```ini
+/// [cosmological_parameters]
+/// omega_m = 0.1 0.3 0.5       ; varied, starting at 0.3
+/// omega_b = 0.045             ; fixed
+/// omega_c = omega_m - omega_b ; derived
+/// ```
+///
+/// Samplers see only the varied parameters, as a vector in the order they appear in the file;
+/// `write_to` fills in the fixed and derived parameters alongside them. Any entry which is not
+/// one to three numbers is read as an `Expression`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParameterSpace {
+    parameters: Vec<Parameter>,
+    /// In an order in which they can be evaluated.
+    derived: Vec<DerivedParameter>
+}
+
+impl ParameterSpace {
+    /// Reads every entry of every section of `ini`. Entries from `[DEFAULT]` are ignored.
+    pub fn from_ini(ini: &Ini) -> Result<Self, IniError> {
+        let mut parameters = Vec::new();
+        let mut derived = Vec::new();
+        for section in ini.sections() {
+            for entry in section.entries() {
+                if entry.value.split_whitespace().all(|word| word.parse::<f64>().is_ok()) {
+                    parameters.push(Parameter::parse(section.name(), &entry.key, &entry.value, entry.location.as_ref())?);
+                    continue;
+                }
+                let expression = Expression::parse(&entry.value).map_err(|err| IniError {
+                    location: entry.location.clone(),
+                    message: format!("{}--{}: {}", section.name(), entry.key, err)
+                })?;
+                derived.push(DerivedParameter { section: section.name().to_string(), name: entry.key.clone(),
+                                                expression, location: entry.location.clone() });
+            }
+        }
+        let derived = order_derived(&parameters, derived)?;
+        Ok(ParameterSpace { parameters, derived })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, IniError> {
+        ParameterSpace::from_ini(&Ini::parse(text)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IniError> {
+        ParameterSpace::from_ini(&Ini::load(path)?)
+    }
+
+    /// Every parameter, varied and fixed, in file order.
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    /// The parameters defined by expressions, in the order they are evaluated.
+    pub fn derived(&self) -> &[DerivedParameter] {
+        &self.derived
+    }
+
+    pub fn varied(&self) -> impl Iterator<Item = &Parameter> + '_ {
+        self.parameters.iter().filter(|p| p.is_varied())
+    }
+
+    pub fn fixed(&self) -> impl Iterator<Item = &Parameter> + '_ {
+        self.parameters.iter().filter(|p| !p.is_varied())
+    }
+
+    /// The length of a parameter vector.
+    pub fn n_varied(&self) -> usize {
+        self.varied().count()
+    }
+
+    /// Section names are compared case-insensitively, as in the `DataBlock`.
+    pub fn get(&self, section: &str, name: &str) -> Option<&Parameter> {
+        self.parameters.iter().find(|p| p.section.eq_ignore_ascii_case(section) && p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The position of a varied parameter in parameter vectors.
+    pub fn index_of(&self, section: &str, name: &str) -> Option<usize> {
+        self.varied().position(|p| p.section.eq_ignore_ascii_case(section) && p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn start(&self) -> Vec<f64> {
+        self.varied().map(|p| p.start).collect()
+    }
+
+    pub fn lower_bounds(&self) -> Vec<f64> {
+        self.varied().filter_map(|p| p.bounds).map(|(min, _)| min).collect()
+    }
+
+    pub fn upper_bounds(&self) -> Vec<f64> {
+        self.varied().filter_map(|p| p.bounds).map(|(_, max)| max).collect()
+    }
+
+    /// Whether every element of the parameter vector `x` lies within its bounds.
+    pub fn in_bounds(&self, x: &[f64]) -> bool {
+        x.len() == self.n_varied() && self.varied().zip(x).all(|(p, &x)| p.contains(x))
+    }
+
+    fn check_len(&self, x: &[f64]) -> CosmosisResult<()> {
+        if x.len() == self.n_varied() {
+            Ok(())
+        } else {
+            Err(CosmosisError::new(DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH)
+                .with_reason(format!("Expected a vector of {} varied parameters, found {}", self.n_varied(), x.len())))
+        }
+    }
+
+    /// Writes the parameter vector `x`, and every fixed and derived parameter, into `db`.
+    /// Existing values are replaced, so one block may be reused for many samples.
+    pub fn write_to(&self, x: &[f64], db: &mut DataBlock) -> CosmosisResult<()> {
+        self.check_len(x)?;
+        let mut x = x.iter();
+        let mut values = HashMap::new();
+        for p in &self.parameters {
+            let value = if p.is_varied() { *x.next().unwrap() } else { p.start };
+            db.put_or_overwrite(p.section.as_str(), p.name.as_str(), value)?;
+            values.insert(parameter_key(&p.section, &p.name), value);
+        }
+        for d in &self.derived {
+            let value = d.expression.evaluate(&|reference| values.get(&d.resolve(reference)).cloned())?;
+            db.put_or_overwrite(d.section.as_str(), d.name.as_str(), value)?;
+            values.insert(parameter_key(&d.section, &d.name), value);
+        }
+        Ok(())
+    }
+
+    /// Reads the varied parameters back out of `db`, as a parameter vector.
+    pub fn read_from(&self, db: &DataBlock) -> CosmosisResult<Vec<f64>> {
+        self.varied().map(|p| db.get::<f64>(p.section.as_str(), p.name.as_str())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParameterSpace;
+    use {DataBlock, DATABLOCK_STATUS};
+
+    const VALUES: &str = "[cosmological_parameters]\n\
+                          omega_m = 0.1 0.3 0.5\n\
+                          h0 = 0.72\n\
+                          w = -2.0 0.0\n\
+                          \n\
+                          [intrinsic_alignment_parameters]\n\
+                          A = -5 1 5\n";
+
+    #[test]
+    fn test_parameter_space() {
+        let space = ParameterSpace::parse(VALUES).unwrap();
+        assert_eq!(space.parameters().len(), 4);
+        assert_eq!(space.n_varied(), 3);
+        assert_eq!(space.fixed().map(|p| p.to_string()).collect::<Vec<_>>(), vec!["cosmological_parameters--h0"]);
+        assert_eq!(space.start(), vec![0.3, -1.0, 1.0]);
+        assert_eq!(space.lower_bounds(), vec![0.1, -2.0, -5.0]);
+        assert_eq!(space.upper_bounds(), vec![0.5, 0.0, 5.0]);
+        assert_eq!(space.index_of("intrinsic_alignment_parameters", "a"), Some(2));
+        assert_eq!(space.index_of("cosmological_parameters", "h0"), None);
+        assert!(space.in_bounds(&[0.5, -2.0, 0.0]));
+        assert!(!space.in_bounds(&[0.6, -2.0, 0.0]));
+        assert!(!space.in_bounds(&[0.3]));
+
+        let mut db = DataBlock::new();
+        space.write_to(&[0.25, -1.5, 2.0], &mut db).unwrap();
+        assert_eq!(db.get::<f64>("cosmological_parameters", "h0").unwrap(), 0.72);
+        assert_eq!(space.read_from(&db).unwrap(), vec![0.25, -1.5, 2.0]);
+        space.write_to(&[0.35, -1.0, 3.0], &mut db).unwrap();
+        assert_eq!(space.read_from(&db).unwrap(), vec![0.35, -1.0, 3.0]);
+        assert_eq!(space.write_to(&[0.3], &mut db).unwrap_err().kind, DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH);
+    }
+
+    #[test]
+    fn test_derived_parameters() {
+        let space = ParameterSpace::parse("[cosmological_parameters]\n\
+                                           omch2 = omega_c * h0^2\n\
+                                           omega_c = omega_m - omega_b\n\
+                                           omega_m = 0.1 0.3 0.5\n\
+                                           omega_b = 0.05\n\
+                                           h0 = 0.7\n\
+                                           [halo_model_parameters]\n\
+                                           a = 2 * cosmological_parameters--omega_m\n").unwrap();
+        assert_eq!(space.n_varied(), 1);
+        assert_eq!(space.derived().iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+                   vec!["cosmological_parameters--omega_c", "cosmological_parameters--omch2", "halo_model_parameters--a"]);
+
+        let mut db = DataBlock::new();
+        space.write_to(&[0.35], &mut db).unwrap();
+        assert!((db.get::<f64>("cosmological_parameters", "omch2").unwrap() - 0.3 * 0.49).abs() < 1e-12);
+        assert_eq!(db.get::<f64>("halo_model_parameters", "a").unwrap(), 0.7);
+
+        for &(text, line, message) in &[("[a]\nx = y + 1\ny = 2 * z\nz = x\n", 2, "a--x -> a--y -> a--z -> a--x"),
+                                        ("[a]\nx = x\n", 2, "a--x -> a--x"),
+                                        ("[a]\nx = 1\ny = b--x\n", 3, "unknown parameter b--x"),
+                                        ("[a]\nx = 1 +\n", 2, "a--x")] {
+            let err = ParameterSpace::parse(text).unwrap_err();
+            assert_eq!(err.line(), Some(line), "{}", err);
+            assert!(err.message.contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        for &(text, line) in &[("[a]\nx = 0.1 zero 0.5\n", 2),
+                               ("[a]\nx = 0.5 0.1\n", 2),
+                               ("[a]\ny = 1\nx = 0.1 0.7 0.5\n", 3),
+                               ("[a]\nx = 1 2 3 4\n", 2)] {
+            let err = ParameterSpace::parse(text).unwrap_err();
+            assert_eq!(err.line(), Some(line), "{}", err);
+            assert!(err.message.contains("a--x"), "{}", err);
+        }
+    }
+}
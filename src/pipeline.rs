@@ -0,0 +1,483 @@
+use std::mem;
+use std::time::Instant;
+
+use super::{CosmosisError, CosmosisResult, DataBlock};
+use constraints::Constraints;
+use dataflow::DataFlow;
+use extra_output::ExtraOutputs;
+use ini::Ini;
+use likelihoods::{LikelihoodBreakdown, LikelihoodSelection};
+use log::LogOperation;
+use native::NativeModule;
+use options::{Options, OPTION_SECTION};
+use parameters::ParameterSpace;
+use priors::Priors;
+use profile::PipelineProfile;
+use snapshot::DebugSnapshots;
+
+/// A stage of a `Pipeline`: reads its inputs from the block, and writes its outputs back.
+///
+/// Modules are set up when constructed, typically from an `Options`, and clean up when dropped.
+pub trait CosmosisModule {
+    /// Identifies the module in errors and in the block's access log.
+    fn name(&self) -> &str;
+
+    fn execute(&mut self, block: &mut DataBlock) -> CosmosisResult<()>;
+}
+
+impl<M: CosmosisModule + ?Sized> CosmosisModule for Box<M> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn execute(&mut self, block: &mut DataBlock) -> CosmosisResult<()> {
+        (**self).execute(block)
+    }
+}
+
+/// A module made from a closure, for small calculations which need no setup.
+pub struct FunctionModule<F> {
+    name: String,
+    function: F
+}
+
+impl<F> FunctionModule<F>
+    where F: FnMut(&mut DataBlock) -> CosmosisResult<()> {
+    pub fn new(name: &str, function: F) -> Self {
+        FunctionModule { name: name.to_string(), function }
+    }
+}
+
+impl<F> CosmosisModule for FunctionModule<F>
+    where F: FnMut(&mut DataBlock) -> CosmosisResult<()> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn execute(&mut self, block: &mut DataBlock) -> CosmosisResult<()> {
+        (self.function)(block)
+    }
+}
+
+/// The outcome of running a `Pipeline` at one point in parameter space.
+#[derive(Debug)]
+pub struct PipelineResult {
+    /// Everything the modules wrote. When the point was rejected, no module has run.
+    pub block: DataBlock,
+    pub log_prior: f64,
+    /// `-inf` when the point was rejected.
+    pub log_likelihood: f64,
+    /// The components of `log_likelihood`; empty when the point was rejected.
+    pub likelihoods: LikelihoodBreakdown,
+    /// The pipeline's extra outputs, in the order of `ExtraOutputs::column_names`; NaN when the
+    /// point was rejected.
+    pub extra_output: Vec<f64>,
+    /// Why the point was rejected without running any module, if it was: because it is outside
+    /// the prior, or violates one of the pipeline's constraints.
+    pub rejected: Option<String>
+}
+
+impl PipelineResult {
+    fn rejected(block: DataBlock, log_prior: f64, extra_outputs: &ExtraOutputs, reason: String) -> Self {
+        PipelineResult { block, log_prior, log_likelihood: f64::NEG_INFINITY, likelihoods: LikelihoodBreakdown::default(),
+                         extra_output: extra_outputs.missing_row(), rejected: Some(reason) }
+    }
+
+    pub fn log_posterior(&self) -> f64 {
+        self.log_prior + self.log_likelihood
+    }
+}
+
+/// A sequence of modules, run in order on a `DataBlock` holding a point in a `ParameterSpace`:
+///
+/// ```
+/// # extern crate cosmosis;
+/// # use cosmosis::{FunctionModule, ParameterSpace, Pipeline};
+/// # fn main() {
+/// let space = ParameterSpace::parse("[params]\nx = -1 0 1\n").unwrap();
+/// let mut pipeline = Pipeline::new(space);
+/// pipeline.add_module(FunctionModule::new("gaussian", |block| {
+///     let x = block.get::<f64>("params", "x")?;
+///     block.put("likelihoods", "gaussian_like", -0.5 * x * x)
+/// }));
+/// assert_eq!(pipeline.run(&[0.5]).unwrap().log_likelihood, -0.125);
+/// # }
+/// ```
+pub struct Pipeline {
+    space: ParameterSpace,
+    priors: Priors,
+    likelihoods: LikelihoodSelection,
+    extra_outputs: ExtraOutputs,
+    constraints: Constraints,
+    modules: Vec<Box<dyn CosmosisModule>>,
+    profile: Option<PipelineProfile>,
+    snapshots: Option<DebugSnapshots>
+}
+
+impl Pipeline {
+    /// A pipeline with no modules, uniform priors over `space`, and a likelihood summed from
+    /// every likelihood the modules write.
+    pub fn new(space: ParameterSpace) -> Self {
+        let priors = Priors::uniform(&space);
+        Pipeline { space, priors, likelihoods: LikelihoodSelection::AllFound, extra_outputs: ExtraOutputs::default(),
+                   constraints: Constraints::default(), modules: Vec::new(), profile: None,
+                   snapshots: None }
+    }
+
+    /// Builds the pipeline described by the `[pipeline]` section of a CosmoSIS ini file: its
+    /// `values` file and optional `priors` file, `likelihoods`, `extra_output` and `constraints`,
+    /// and its `modules`. Each module is loaded as a `NativeModule` from the `file` in its own
+    /// section, and set up with that section's options in `OPTION_SECTION`. Relative paths are
+    /// resolved against the ini file's directory.
+    pub fn from_ini(ini: &Ini) -> CosmosisResult<Self> {
+        let block = ini.to_datablock()?;
+        let options = |section: &str| match ini.directory() {
+            Some(dir) => Options::for_section(&block, section).relative_to(dir),
+            None => Options::for_section(&block, section)
+        };
+        let pipeline = options("pipeline");
+
+        let space = ParameterSpace::load(pipeline.get_path("values")?)?;
+        let priors = if pipeline.contains("priors") {
+            Priors::load(pipeline.get_path("priors")?, &space)?
+        } else {
+            Priors::uniform(&space)
+        };
+        let likelihoods = LikelihoodSelection::from_option(pipeline.get_optional::<String>("likelihoods")?.as_deref());
+        let extra_outputs = ExtraOutputs::parse(&pipeline.get_or("extra_output", String::new())?)?;
+        let constraints = Constraints::parse(&pipeline.get_or("constraints", String::new())?)?;
+        let mut result = Pipeline::new(space).with_priors(priors).with_likelihoods(likelihoods)
+                                             .with_extra_outputs(extra_outputs).with_constraints(constraints);
+
+        for name in pipeline.get_list::<String>("modules")? {
+            let file = options(&name).get_path("file")?;
+            let mut setup = ini.clone();
+            for entry in ini.items(&name) {
+                setup.set(OPTION_SECTION, &entry.key, &entry.value);
+            }
+            result.add_module(NativeModule::load(file, &setup.to_datablock()?)?.with_name(&name));
+        }
+        Ok(result)
+    }
+
+    /// Replaces the default uniform priors, e.g. with those from a priors file.
+    pub fn with_priors(self, priors: Priors) -> Self {
+        Pipeline { priors, ..self }
+    }
+
+    /// Chooses which likelihoods make up the total, e.g. from the `likelihoods` option.
+    pub fn with_likelihoods(self, likelihoods: LikelihoodSelection) -> Self {
+        Pipeline { likelihoods, ..self }
+    }
+
+    /// Sets the derived quantities to extract after each run, e.g. from the `extra_output` option.
+    pub fn with_extra_outputs(self, extra_outputs: ExtraOutputs) -> Self {
+        Pipeline { extra_outputs, ..self }
+    }
+
+    /// Sets conditions which each point must meet to be run, e.g. from the `constraints` option.
+    pub fn with_constraints(self, constraints: Constraints) -> Self {
+        Pipeline { constraints, ..self }
+    }
+
+    /// Records how long each module takes, and how much it reads and writes, on every evaluation
+    /// from now on; see `profile`.
+    pub fn with_profiling(self) -> Self {
+        Pipeline { profile: Some(PipelineProfile::default()), ..self }
+    }
+
+    /// Saves the block after every module, for debugging; see `DebugSnapshots`.
+    pub fn with_debug_snapshots(self, snapshots: DebugSnapshots) -> Self {
+        Pipeline { snapshots: Some(snapshots), ..self }
+    }
+
+    /// Appends a module, to run after those already added.
+    pub fn add_module(&mut self, module: impl CosmosisModule + 'static) -> &mut Self {
+        self.modules.push(Box::new(module));
+        self
+    }
+
+    pub fn parameters(&self) -> &ParameterSpace {
+        &self.space
+    }
+
+    pub fn priors(&self) -> &Priors {
+        &self.priors
+    }
+
+    pub fn extra_outputs(&self) -> &ExtraOutputs {
+        &self.extra_outputs
+    }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    pub fn module_names(&self) -> Vec<&str> {
+        self.modules.iter().map(|module| module.name()).collect()
+    }
+
+    /// Everything recorded since profiling was enabled, or `None` if it is not.
+    pub fn profile(&self) -> Option<&PipelineProfile> {
+        self.profile.as_ref()
+    }
+
+    /// The snapshots of the latest evaluation, if debug snapshots are enabled.
+    pub fn debug_snapshots(&self) -> Option<&DebugSnapshots> {
+        self.snapshots.as_ref()
+    }
+
+    /// Returns the profile recorded so far, if profiling is enabled, and starts a fresh one.
+    pub fn take_profile(&mut self) -> Option<PipelineProfile> {
+        self.profile.as_mut().map(mem::take)
+    }
+
+    /// Runs every module, in order, on `block`. Stops at the first module to fail, naming it in
+    /// the error. A `MODULE-START` entry is logged before each module runs, so that the block's
+    /// access log shows which module made each access.
+    ///
+    /// When profiling, each module is timed and its accesses counted, whether or not it succeeds.
+    /// With debug snapshots, the block is saved before the first module and after each one,
+    /// including one which fails.
+    pub fn execute(&mut self, block: &mut DataBlock) -> CosmosisResult<()> {
+        if let Some(ref mut profile) = self.profile {
+            profile.start_evaluation();
+        }
+        if let Some(ref mut snapshots) = self.snapshots {
+            snapshots.start(block)?;
+        }
+        for module in &mut self.modules {
+            block.log_access(LogOperation::ModuleStart.as_str(), module.name(), "")?;
+            let log_start = block.log_len();
+            let start = Instant::now();
+            let result = module.execute(block);
+            let elapsed = start.elapsed();
+            if let Some(ref mut profile) = self.profile {
+                // As with snapshots, a module's own error takes precedence over a failure to profile it.
+                match block.access_log_since(log_start) {
+                    Ok(accesses) => profile.record(module.name(), elapsed, &accesses, block),
+                    Err(err) => if result.is_ok() { return Err(err); }
+                }
+            }
+            if let Some(ref mut snapshots) = self.snapshots {
+                if result.is_ok() {
+                    snapshots.record(module.name(), block, false)?;
+                } else {
+                    // The module's own error is the one to report, even if its snapshot can't be saved.
+                    let _ = snapshots.record(module.name(), block, true);
+                }
+            }
+            result.map_err(|err| {
+                let reason = match err.reason {
+                    Some(ref reason) => format!("Module {} failed: {}", module.name(), reason),
+                    None => format!("Module {} failed: {}", module.name(), err.kind)
+                };
+                CosmosisError::new(err.kind).with_reason(reason)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Runs every module once at `x`, ignoring priors and constraints, and records which values
+    /// each one read and wrote. Modules which run in the wrong order show up in the result's
+    /// warnings, and `DataFlow::to_dot` draws the pipeline. This run is neither profiled nor
+    /// snapshotted.
+    pub fn data_flow(&mut self, x: &[f64]) -> CosmosisResult<DataFlow> {
+        let mut block = DataBlock::new();
+        self.space.write_to(x, &mut block)?;
+        self.priors.write_to(x, &mut block)?;
+        let (profile, snapshots) = (self.profile.take(), self.snapshots.take());
+        let result = self.execute(&mut block);
+        self.profile = profile;
+        self.snapshots = snapshots;
+        result?;
+        Ok(DataFlow::from_log(&block.access_log()?))
+    }
+
+    /// Fills a fresh block with the parameter vector `x` and its priors, runs every module on it,
+    /// and collects the log-likelihood. Points outside the prior, or which violate a constraint,
+    /// are not run; a violated constraint is also logged as a `CONSTRAINT-FAILED` entry.
+    pub fn run(&mut self, x: &[f64]) -> CosmosisResult<PipelineResult> {
+        let mut block = DataBlock::new();
+        self.space.write_to(x, &mut block)?;
+        let log_prior = self.priors.write_to(x, &mut block)?;
+        if log_prior == f64::NEG_INFINITY {
+            let reason = "outside the prior".to_string();
+            return Ok(PipelineResult::rejected(block, log_prior, &self.extra_outputs, reason));
+        }
+        if let Some(constraint) = self.constraints.check(&block)? {
+            block.log_access(LogOperation::ConstraintFailed.as_str(), "constraints", constraint.to_string())?;
+            let reason = format!("violates constraint {}", constraint);
+            return Ok(PipelineResult::rejected(block, log_prior, &self.extra_outputs, reason));
+        }
+        self.execute(&mut block)?;
+        let likelihoods = block.likelihoods(&self.likelihoods)?;
+        let extra_output = self.extra_outputs.extract(&block)?;
+        Ok(PipelineResult { block, log_prior, log_likelihood: likelihoods.total, likelihoods, extra_output,
+                            rejected: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionModule, Pipeline};
+    use std::{env, fs};
+    use {Constraints, CosmosisError, DataBlock, DATABLOCK_STATUS, DebugSnapshots, ExtraOutputs, Ini,
+         LikelihoodSelection, LogOperation, ParameterSpace, SnapshotFormat};
+
+    fn pipeline() -> Pipeline {
+        let space = ParameterSpace::parse("[cosmological_parameters]\n\
+                                           omega_m = 0.1 0.3 0.5\n\
+                                           h0 = 0.7\n").unwrap();
+        let mut pipeline = Pipeline::new(space);
+        pipeline.add_module(FunctionModule::new("theory", |block: &mut DataBlock| {
+                    let omega_m = block.get::<f64>("cosmological_parameters", "omega_m")?;
+                    let h0 = block.get::<f64>("cosmological_parameters", "h0")?;
+                    block.put("derived", "omch2", omega_m * h0 * h0)
+                }))
+                .add_module(FunctionModule::new("like", |block: &mut DataBlock| {
+                    let omch2 = block.get::<f64>("derived", "omch2")?;
+                    block.put("likelihoods", "omch2_like", -0.5 * ((omch2 - 0.147) / 0.01).powi(2))?;
+                    block.put("likelihoods", "other_like", -1.0)
+                }));
+        pipeline
+    }
+
+    #[test]
+    fn test_run() {
+        let mut pipeline = pipeline();
+        assert_eq!(pipeline.module_names(), vec!["theory", "like"]);
+
+        let result = pipeline.run(&[0.3]).unwrap();
+        assert!((result.log_likelihood - -1.0).abs() < 1e-12);
+        assert_eq!(result.log_prior, -(0.4f64).ln());
+        assert!((result.block.get::<f64>("derived", "omch2").unwrap() - 0.147).abs() < 1e-12);
+        assert_eq!(result.likelihoods.get("other"), Some(-1.0));
+
+        let mut pipeline = pipeline.with_likelihoods(LikelihoodSelection::from_option(Some("other")))
+                                   .with_extra_outputs(ExtraOutputs::parse("derived/omch2").unwrap());
+        let result = pipeline.run(&[0.3]).unwrap();
+        assert_eq!(result.log_likelihood, -1.0);
+        assert_eq!(result.extra_output.len(), 1);
+        assert!((result.extra_output[0] - 0.147).abs() < 1e-12);
+
+        let outside = pipeline.run(&[0.6]).unwrap();
+        assert!(outside.extra_output[0].is_nan());
+        assert_eq!(outside.log_posterior(), f64::NEG_INFINITY);
+        assert!(!outside.block.contains_section("derived"));
+        assert_eq!(outside.rejected.as_deref(), Some("outside the prior"));
+    }
+
+    #[test]
+    fn test_constraints() {
+        let constraints = Constraints::parse("cosmological_parameters--omega_m * cosmological_parameters--h0^2 < 0.2")
+                                     .unwrap();
+        let mut pipeline = pipeline().with_constraints(constraints);
+        assert!(pipeline.run(&[0.3]).unwrap().rejected.is_none());
+
+        let result = pipeline.run(&[0.45]).unwrap();
+        assert_eq!(result.log_posterior(), f64::NEG_INFINITY);
+        assert!(result.rejected.unwrap().contains("violates constraint cosmological_parameters--omega_m"));
+        assert!(!result.block.contains_section("derived"));
+        let log = result.block.access_log().unwrap();
+        let entry = log.iter().find(|entry| entry.operation == LogOperation::ConstraintFailed).unwrap();
+        assert_eq!(entry.section, "constraints");
+    }
+
+    #[test]
+    fn test_profiling() {
+        let mut pipeline = pipeline();
+        pipeline.run(&[0.3]).unwrap();
+        assert!(pipeline.profile().is_none());
+
+        let mut pipeline = pipeline.with_profiling();
+        pipeline.run(&[0.3]).unwrap();
+        pipeline.run(&[0.35]).unwrap();
+        pipeline.run(&[0.6]).unwrap();
+        let profile = pipeline.take_profile().unwrap();
+        assert_eq!(profile.evaluations(), 2);
+        let names: Vec<_> = profile.modules().iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, vec!["theory", "like"]);
+        let theory = profile.get("theory").unwrap();
+        assert_eq!((theory.calls, theory.reads, theory.writes, theory.bytes_written), (2, 4, 2, 16));
+        assert_eq!(profile.get("like").unwrap().writes, 4);
+        assert_eq!(pipeline.profile().unwrap().evaluations(), 0);
+    }
+
+    #[test]
+    fn test_from_ini() {
+        let dir = env::temp_dir().join(format!("cosmosis-pipeline-test-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("values.ini"), "[params]\nx = -1 0 1\ny = 2\n").unwrap();
+        fs::write(dir.join("pipeline.ini"), "[pipeline]\nmodules =\nvalues = values.ini\n\
+                                             extra_output = params/y\nconstraints = params--x < 0.5\n\
+                                             [broken]\nsetting = 1\n").unwrap();
+
+        let mut ini = Ini::load(dir.join("pipeline.ini")).unwrap();
+        let mut pipeline = Pipeline::from_ini(&ini).unwrap();
+        assert_eq!(pipeline.parameters().n_varied(), 1);
+        let result = pipeline.run(&[0.0]).unwrap();
+        assert_eq!((result.log_likelihood, result.extra_output[0]), (0.0, 2.0));
+        assert!(pipeline.run(&[0.7]).unwrap().rejected.is_some());
+
+        ini.set("pipeline", "modules", "broken");
+        let err = Pipeline::from_ini(&ini).err().unwrap();
+        assert!(err.to_string().contains("Option [broken] file"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_data_flow() {
+        let dir = env::temp_dir().join(format!("cosmosis-data-flow-test-{}", ::std::process::id()));
+        let mut pipeline = pipeline().with_profiling().with_debug_snapshots(DebugSnapshots::new(&dir));
+        pipeline.add_module(FunctionModule::new("late", |block: &mut DataBlock| {
+            let omch2 = block.get::<f64>("derived", "omch2")?;
+            block.overwrite("derived", "omch2", 2.0 * omch2)
+        }));
+        let flow = pipeline.data_flow(&[0.3]).unwrap();
+        let stages: Vec<_> = flow.stages().iter().map(|stage| stage.stage.as_str()).collect();
+        assert_eq!(stages, vec!["parameters", "theory", "like", "late"]);
+        assert!(flow.stages()[0].writes.contains(&("cosmological_parameters".to_string(), "h0".to_string())));
+        let warnings: Vec<_> = flow.warnings().iter().map(ToString::to_string).collect();
+        assert_eq!(warnings, vec!["late overwrites derived/omch2, written by theory"]);
+        assert!(flow.to_dot().contains("\"value:derived/omch2\" -> \"module:like\""));
+
+        assert_eq!(pipeline.profile().unwrap().evaluations(), 0);
+        assert!(pipeline.debug_snapshots().unwrap().snapshots().is_empty());
+        assert!(!dir.exists());
+        pipeline.run(&[0.3]).unwrap();
+        assert_eq!(pipeline.profile().unwrap().evaluations(), 1);
+        assert!(dir.join("evaluation_1").is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_failure() {
+        let dir = env::temp_dir().join(format!("cosmosis-snapshot-test-{}", ::std::process::id()));
+        let snapshots = DebugSnapshots::new(&dir).format(SnapshotFormat::Json);
+        let mut pipeline = pipeline().with_debug_snapshots(snapshots);
+        pipeline.add_module(FunctionModule::new("broken", |block: &mut DataBlock| {
+            block.put("broken", "partial", 1)?;
+            Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR).with_reason("out of range".to_string()))
+        }));
+        let err = pipeline.run(&[0.3]).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_LOGIC_ERROR);
+        assert!(err.to_string().contains("Module broken failed: out of range"), "{}", err);
+
+        let snapshots = pipeline.debug_snapshots().unwrap();
+        let stages: Vec<_> = snapshots.snapshots().iter().map(|snapshot| snapshot.stage.as_str()).collect();
+        assert_eq!(stages, vec!["parameters", "theory", "like", "broken"]);
+        assert_eq!(snapshots.snapshots()[1].diff.to_string(), "+ [derived] (section added)");
+        assert!(snapshots.last_good().unwrap().contains("likelihoods", "omch2_like"));
+        assert!(!snapshots.last_good().unwrap().contains_section("broken"));
+        assert!(snapshots.snapshots()[3].failed);
+
+        let evaluation = dir.join("evaluation_1");
+        let diff = fs::read_to_string(evaluation.join("03_broken.failed.diff")).unwrap();
+        assert_eq!(diff, "+ [broken] (section added)\n");
+        let partial = DataBlock::from_json(&fs::read_to_string(evaluation.join("03_broken.failed.json")).unwrap());
+        assert_eq!(partial.unwrap().get::<i32>("broken", "partial").unwrap(), 1);
+        assert!(evaluation.join("02_like.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,462 @@
+use std::f64::consts::{PI, SQRT_2};
+use std::fmt;
+use std::path::Path;
+
+use libm;
+use rand::{Rng, RngCore};
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use ini::{Ini, IniError};
+use names::{LIKELIHOODS_SECTION, PRIORS_SECTION};
+use parameters::{Parameter, ParameterSpace};
+
+/// Name under which the total log-prior is stored in `LIKELIHOODS_SECTION`. It has no
+/// `LIKELIHOOD_SUFFIX`, so it is not mistaken for a likelihood.
+pub const PRIOR_NAME: &str = "prior";
+
+/// A one-dimensional prior distribution over a parameter.
+pub trait Prior: fmt::Debug {
+    /// The log of the (normalized) density at `x`; `-inf` outside the prior's support.
+    fn log_pdf(&self, x: f64) -> f64;
+
+    /// The probability that the parameter is at most `x`.
+    fn cdf(&self, x: f64) -> f64;
+
+    /// The inverse of `cdf`: maps `u` from the unit interval onto the prior, so that uniform
+    /// `u` gives values distributed according to the prior. Nested samplers work in these
+    /// coordinates.
+    fn transform(&self, u: f64) -> f64;
+
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        self.transform(rng.gen())
+    }
+}
+
+impl<P: Prior + ?Sized> Prior for Box<P> {
+    fn log_pdf(&self, x: f64) -> f64 {
+        (**self).log_pdf(x)
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        (**self).cdf(x)
+    }
+
+    fn transform(&self, u: f64) -> f64 {
+        (**self).transform(u)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uniform {
+    pub lower: f64,
+    pub upper: f64
+}
+
+impl Prior for Uniform {
+    fn log_pdf(&self, x: f64) -> f64 {
+        if self.lower <= x && x <= self.upper {
+            -(self.upper - self.lower).ln()
+        } else {
+            f64::NEG_INFINITY
+        }
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        ((x - self.lower) / (self.upper - self.lower)).clamp(0.0, 1.0)
+    }
+
+    fn transform(&self, u: f64) -> f64 {
+        self.lower + u * (self.upper - self.lower)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gaussian {
+    pub mean: f64,
+    pub sigma: f64
+}
+
+impl Prior for Gaussian {
+    fn log_pdf(&self, x: f64) -> f64 {
+        let z = (x - self.mean) / self.sigma;
+        -0.5 * z * z - self.sigma.ln() - 0.5 * (2.0 * PI).ln()
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        0.5 * libm::erfc((self.mean - x) / (self.sigma * SQRT_2))
+    }
+
+    fn transform(&self, u: f64) -> f64 {
+        self.mean + self.sigma * inverse_normal_cdf(u)
+    }
+}
+
+/// The exponential distribution on `x >= 0`, with density `exp(-x / beta) / beta`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exponential {
+    pub beta: f64
+}
+
+impl Prior for Exponential {
+    fn log_pdf(&self, x: f64) -> f64 {
+        if x >= 0.0 {
+            -x / self.beta - self.beta.ln()
+        } else {
+            f64::NEG_INFINITY
+        }
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        if x > 0.0 { -(-x / self.beta).exp_m1() } else { 0.0 }
+    }
+
+    fn transform(&self, u: f64) -> f64 {
+        -self.beta * (-u).ln_1p()
+    }
+}
+
+/// Another prior, restricted to `[lower, upper]` and renormalized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Truncated<P: Prior> {
+    prior: P,
+    lower: f64,
+    upper: f64,
+    cdf_lower: f64,
+    /// The probability `prior` assigns to `[lower, upper]`.
+    mass: f64
+}
+
+impl<P: Prior> Truncated<P> {
+    /// Fails if `prior` assigns no probability to `[lower, upper]`.
+    pub fn new(prior: P, lower: f64, upper: f64) -> CosmosisResult<Self> {
+        let cdf_lower = prior.cdf(lower);
+        let mass = prior.cdf(upper) - cdf_lower;
+        if lower > upper || mass.is_nan() || mass <= 0.0 {
+            return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+                       .with_reason(format!("{:?} has no probability between {} and {}", prior, lower, upper)));
+        }
+        Ok(Truncated { prior, lower, upper, cdf_lower, mass })
+    }
+
+    pub fn bounds(&self) -> (f64, f64) {
+        (self.lower, self.upper)
+    }
+}
+
+impl<P: Prior> Prior for Truncated<P> {
+    fn log_pdf(&self, x: f64) -> f64 {
+        if self.lower <= x && x <= self.upper {
+            self.prior.log_pdf(x) - self.mass.ln()
+        } else {
+            f64::NEG_INFINITY
+        }
+    }
+
+    fn cdf(&self, x: f64) -> f64 {
+        ((self.prior.cdf(x) - self.cdf_lower) / self.mass).clamp(0.0, 1.0)
+    }
+
+    fn transform(&self, u: f64) -> f64 {
+        self.prior.transform(self.cdf_lower + u * self.mass).max(self.lower).min(self.upper)
+    }
+}
+
+/// The quantile function of the standard normal distribution: Acklam's rational approximation,
+/// polished with one step of Halley's method to full double precision.
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+                         1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+                         6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+                         -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+                         3.754408661907416e+00];
+    const P_LOW: f64 = 0.02425;
+
+    if p.is_nan() || !(0.0..=1.0).contains(&p) {
+        return f64::NAN;
+    } else if p == 0.0 {
+        return f64::NEG_INFINITY;
+    } else if p == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    let x = if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (-p).ln_1p()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+
+    let e = 0.5 * libm::erfc(-x / SQRT_2) - p;
+    let u = e * (2.0 * PI).sqrt() * (0.5 * x * x).exp();
+    x - u / (1.0 + 0.5 * x * u)
+}
+
+/// The prior on one varied parameter.
+#[derive(Debug)]
+pub struct ParameterPrior {
+    pub section: String,
+    pub name: String,
+    pub prior: Box<dyn Prior>
+}
+
+/// Priors on every varied parameter of a `ParameterSpace`, read from a CosmoSIS priors file:
+///
+/// ```ini
+/// [cosmological_parameters]
+/// omega_m = gaussian 0.3 0.05
+/// h0 = uniform 0.6 0.8
+/// tau = exponential 0.1
+/// w = truncated_gaussian -1.0 0.3 -1.5 -0.5
+/// ```
+///
+/// `gaussian` (or `normal`) takes a mean and standard deviation, `uniform` its bounds,
+/// `exponential` a scale `beta`, and the `truncated_` forms further take bounds. As in CosmoSIS,
+/// every prior is also truncated to the parameter's range from the values file, and parameters
+/// missing from the priors file are uniform over that range. Priors on fixed parameters are
+/// ignored.
+#[derive(Debug)]
+pub struct Priors {
+    priors: Vec<ParameterPrior>
+}
+
+impl Priors {
+    /// Uniform priors over the bounds of every varied parameter, for pipelines without a priors
+    /// file.
+    pub fn uniform(space: &ParameterSpace) -> Self {
+        Priors { priors: space.varied().map(uniform_prior).collect() }
+    }
+
+    pub fn from_ini(ini: &Ini, space: &ParameterSpace) -> Result<Self, IniError> {
+        let mut priors = Priors::uniform(space);
+        for section in ini.sections() {
+            for entry in section.entries() {
+                let error = |message: String| IniError {
+                    location: entry.location.clone(),
+                    message: format!("{}--{}: {}", section.name(), entry.key, message)
+                };
+                let parameter = space.get(section.name(), &entry.key)
+                                     .ok_or_else(|| error("no such parameter in the values file".to_string()))?;
+                let bounds = match parameter.bounds {
+                    Some(bounds) => bounds,
+                    None => continue
+                };
+                let prior = parse_prior(&entry.value, bounds).map_err(error)?;
+                let i = space.index_of(section.name(), &entry.key).expect("varied parameters have an index");
+                priors.priors[i].prior = prior;
+            }
+        }
+        Ok(priors)
+    }
+
+    pub fn parse(text: &str, space: &ParameterSpace) -> Result<Self, IniError> {
+        Priors::from_ini(&Ini::parse(text)?, space)
+    }
+
+    pub fn load(path: impl AsRef<Path>, space: &ParameterSpace) -> Result<Self, IniError> {
+        Priors::from_ini(&Ini::load(path)?, space)
+    }
+
+    /// One prior per varied parameter, in parameter vector order.
+    pub fn priors(&self) -> &[ParameterPrior] {
+        &self.priors
+    }
+
+    pub fn get(&self, section: &str, name: &str) -> Option<&dyn Prior> {
+        self.priors.iter()
+            .find(|p| p.section.eq_ignore_ascii_case(section) && p.name.eq_ignore_ascii_case(name))
+            .map(|p| &*p.prior)
+    }
+
+    fn check_len(&self, x: &[f64]) -> CosmosisResult<()> {
+        if x.len() == self.priors.len() {
+            Ok(())
+        } else {
+            Err(CosmosisError::new(DATABLOCK_STATUS::DBS_EXTENTS_MISMATCH)
+                .with_reason(format!("Expected a vector of {} varied parameters, found {}", self.priors.len(), x.len())))
+        }
+    }
+
+    /// The log-prior of each element of the parameter vector `x`.
+    pub fn log_priors(&self, x: &[f64]) -> CosmosisResult<Vec<f64>> {
+        self.check_len(x)?;
+        Ok(self.priors.iter().zip(x).map(|(p, &x)| p.prior.log_pdf(x)).collect())
+    }
+
+    /// The total log-prior of the parameter vector `x`.
+    pub fn log_prior(&self, x: &[f64]) -> CosmosisResult<f64> {
+        self.log_priors(x).map(|log_priors| log_priors.iter().sum())
+    }
+
+    /// Maps a point `u` of the unit hypercube to a parameter vector; see `Prior::transform`.
+    pub fn transform(&self, u: &[f64]) -> CosmosisResult<Vec<f64>> {
+        self.check_len(u)?;
+        Ok(self.priors.iter().zip(u).map(|(p, &u)| p.prior.transform(u)).collect())
+    }
+
+    /// Draws a parameter vector from the priors.
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Vec<f64> {
+        self.priors.iter().map(|p| p.prior.sample(rng)).collect()
+    }
+
+    /// Writes the log-prior of each parameter in `x` to `PRIORS_SECTION`, named
+    /// `section--name`, and their total to `PRIOR_NAME` in `LIKELIHOODS_SECTION`. Returns the
+    /// total.
+    pub fn write_to(&self, x: &[f64], db: &mut DataBlock) -> CosmosisResult<f64> {
+        let log_priors = self.log_priors(x)?;
+        for (p, &log_prior) in self.priors.iter().zip(&log_priors) {
+            db.put_or_overwrite(PRIORS_SECTION, format!("{}--{}", p.section, p.name), log_prior)?;
+        }
+        let total = log_priors.iter().sum();
+        db.put_or_overwrite(LIKELIHOODS_SECTION, PRIOR_NAME, total)?;
+        Ok(total)
+    }
+}
+
+fn uniform_prior(parameter: &Parameter) -> ParameterPrior {
+    let (lower, upper) = parameter.bounds.expect("only varied parameters have priors");
+    ParameterPrior { section: parameter.section.clone(), name: parameter.name.clone(),
+                     prior: Box::new(Uniform { lower, upper }) }
+}
+
+/// Parses one entry of a priors file, truncated to `bounds` from the values file.
+fn parse_prior(value: &str, bounds: (f64, f64)) -> Result<Box<dyn Prior>, String> {
+    let mut words = value.split_whitespace();
+    let kind = words.next().unwrap_or("").to_lowercase();
+    let numbers = words.map(|word| word.parse::<f64>().map_err(|_| format!("{:?} is not a number", word)))
+                       .collect::<Result<Vec<_>, _>>()?;
+    let expect = |n: usize, usage: &str| if numbers.len() == n {
+        Ok(())
+    } else {
+        Err(format!("expected `{}`, found {:?}", usage, value))
+    };
+    let positive = |x: f64, what: &str| if x > 0.0 {
+        Ok(x)
+    } else {
+        Err(format!("{} must be positive, found {}", what, x))
+    };
+    let truncate = |prior: Box<dyn Prior>, lower: f64, upper: f64| {
+        Truncated::new(prior, lower.max(bounds.0), upper.min(bounds.1))
+            .map(|prior| Box::new(prior) as Box<dyn Prior>)
+            .map_err(|_| format!("no probability within the values file range {} to {}", bounds.0, bounds.1))
+    };
+
+    match kind.as_str() {
+        "uniform" => {
+            expect(2, "uniform lower upper")?;
+            let (lower, upper) = (numbers[0].max(bounds.0), numbers[1].min(bounds.1));
+            if lower < upper {
+                Ok(Box::new(Uniform { lower, upper }))
+            } else {
+                Err(format!("uniform range does not overlap the values file range {} to {}", bounds.0, bounds.1))
+            }
+        },
+        "gaussian" | "normal" => {
+            expect(2, "gaussian mean sigma")?;
+            let sigma = positive(numbers[1], "sigma")?;
+            truncate(Box::new(Gaussian { mean: numbers[0], sigma }), bounds.0, bounds.1)
+        },
+        "truncated_gaussian" => {
+            expect(4, "truncated_gaussian mean sigma lower upper")?;
+            let sigma = positive(numbers[1], "sigma")?;
+            truncate(Box::new(Gaussian { mean: numbers[0], sigma }), numbers[2], numbers[3])
+        },
+        "exponential" => {
+            expect(1, "exponential beta")?;
+            let beta = positive(numbers[0], "beta")?;
+            truncate(Box::new(Exponential { beta }), bounds.0, bounds.1)
+        },
+        "truncated_exponential" => {
+            expect(3, "truncated_exponential beta lower upper")?;
+            let beta = positive(numbers[0], "beta")?;
+            truncate(Box::new(Exponential { beta }), numbers[1], numbers[2])
+        },
+        _ => Err(format!("unknown prior {:?}; expected uniform, gaussian, exponential, truncated_gaussian \
+                          or truncated_exponential", kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inverse_normal_cdf, Exponential, Gaussian, Prior, Priors, Truncated, Uniform};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use {DataBlock, ParameterSpace};
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-12 * (1.0 + b.abs())
+    }
+
+    #[test]
+    fn test_distributions() {
+        let gaussian = Gaussian { mean: 1.0, sigma: 2.0 };
+        assert!(close(gaussian.log_pdf(1.0), -(2.0 * (2.0 * ::std::f64::consts::PI).sqrt()).ln()));
+        assert!(close(gaussian.cdf(1.0), 0.5));
+        assert!(close(inverse_normal_cdf(0.975), 1.959963984540054));
+        for &u in &[1e-10, 0.01, 0.3, 0.5, 0.9, 0.999] {
+            assert!(close(gaussian.cdf(gaussian.transform(u)), u), "{}", u);
+        }
+
+        let uniform = Uniform { lower: 2.0, upper: 6.0 };
+        assert_eq!(uniform.log_pdf(3.0), -(4.0f64).ln());
+        assert_eq!(uniform.log_pdf(7.0), f64::NEG_INFINITY);
+        assert_eq!(uniform.transform(0.25), 3.0);
+
+        let exponential = Exponential { beta: 0.5 };
+        assert_eq!(exponential.log_pdf(-0.1), f64::NEG_INFINITY);
+        assert!(close(exponential.cdf(exponential.transform(0.7)), 0.7));
+
+        let truncated = Truncated::new(gaussian, 1.0, f64::INFINITY).unwrap();
+        assert!(close(truncated.log_pdf(2.0), gaussian.log_pdf(2.0) + (2.0f64).ln()));
+        assert_eq!(truncated.log_pdf(0.5), f64::NEG_INFINITY);
+        assert!(close(truncated.transform(0.0), 1.0));
+        assert!(Truncated::new(Exponential { beta: 1.0 }, -2.0, -1.0).is_err());
+    }
+
+    #[test]
+    fn test_priors_file() {
+        let space = ParameterSpace::parse("[cosmological_parameters]\n\
+                                           omega_m = 0.1 0.3 0.5\n\
+                                           h0 = 0.5 0.7 0.9\n\
+                                           tau = 0.0 0.05 0.2\n\
+                                           w = -1.0\n").unwrap();
+        let priors = Priors::parse("[cosmological_parameters]\n\
+                                    omega_m = gaussian 0.3 0.05\n\
+                                    tau = exponential 0.1\n\
+                                    w = gaussian -1 0.1\n", &space).unwrap();
+        assert_eq!(priors.priors().len(), 3);
+        assert_eq!(priors.get("cosmological_parameters", "h0").unwrap().log_pdf(0.6), -(0.4f64).ln());
+
+        let x = [0.3, 0.7, 0.05];
+        let total = priors.log_prior(&x).unwrap();
+        let mut db = DataBlock::new();
+        assert_eq!(priors.write_to(&x, &mut db).unwrap(), total);
+        assert_eq!(db.get::<f64>("likelihoods", "prior").unwrap(), total);
+        assert_eq!(db.get::<f64>("priors", "cosmological_parameters--h0").unwrap(), -(0.4f64).ln());
+        assert_eq!(priors.log_prior(&[0.6, 0.7, 0.05]).unwrap(), f64::NEG_INFINITY);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            assert!(space.in_bounds(&priors.sample(&mut rng)));
+        }
+        assert_eq!(priors.transform(&[0.0, 0.5, 1.0]).unwrap()[1], 0.7);
+
+        for &(text, line) in &[("[cosmological_parameters]\nomega_m = cauchy 0.3 0.1\n", 2),
+                               ("[cosmological_parameters]\n\nomega_m = gaussian 0.3\n", 3),
+                               ("[cosmological_parameters]\nomega_m = uniform 0.6 0.8\n", 2),
+                               ("[cosmological_parameters]\nomega_l = uniform 0.6 0.8\n", 2)] {
+            let err = Priors::parse(text, &space).unwrap_err();
+            assert_eq!(err.line(), Some(line), "{}", err);
+        }
+    }
+}
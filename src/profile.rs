@@ -0,0 +1,202 @@
+use std::fmt;
+use std::mem;
+use std::os::raw;
+use std::time::Duration;
+
+use serde_json::{Map, Value as Json};
+
+use super::{bindings, Complex, DataBlock, datablock_type_t};
+use key::with_keys;
+use log::{LogEntry, LogOperation};
+
+/// Timings and `DataBlock` traffic of one module, accumulated over every evaluation profiled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleProfile {
+    pub name: String,
+    /// How many times the module ran.
+    pub calls: usize,
+    /// Wall-clock time spent in the module, in total and in its fastest and slowest calls.
+    pub total_time: Duration,
+    pub min_time: Duration,
+    pub max_time: Duration,
+    /// Successful reads, and writes or replacements, as recorded in the access log.
+    pub reads: usize,
+    pub writes: usize,
+    /// The size of the values read and written. Strings are not counted, since measuring one
+    /// would mean reading it, and so adding to the access log.
+    pub bytes_read: usize,
+    pub bytes_written: usize
+}
+
+impl ModuleProfile {
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::default()
+        } else {
+            self.total_time / self.calls as u32
+        }
+    }
+
+    /// Adds one call, which took `time` and made the accesses in `entries`.
+    fn record(&mut self, time: Duration, entries: &[LogEntry], block: &DataBlock) {
+        self.min_time = if self.calls == 0 { time } else { self.min_time.min(time) };
+        self.max_time = self.max_time.max(time);
+        self.total_time += time;
+        self.calls += 1;
+        for entry in entries {
+            let size = || block.value_size(entry.section.as_str(), entry.name.as_str()).unwrap_or(0);
+            match entry.operation {
+                LogOperation::Read | LogOperation::ReadDefault => {
+                    self.reads += 1;
+                    self.bytes_read += size();
+                },
+                LogOperation::Write | LogOperation::Replace => {
+                    self.writes += 1;
+                    self.bytes_written += size();
+                },
+                _ => ()
+            }
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut json = Map::new();
+        json.insert("name".to_string(), Json::from(self.name.as_str()));
+        json.insert("calls".to_string(), Json::from(self.calls));
+        json.insert("total_seconds".to_string(), Json::from(self.total_time.as_secs_f64()));
+        json.insert("mean_seconds".to_string(), Json::from(self.mean_time().as_secs_f64()));
+        json.insert("min_seconds".to_string(), Json::from(self.min_time.as_secs_f64()));
+        json.insert("max_seconds".to_string(), Json::from(self.max_time.as_secs_f64()));
+        json.insert("reads".to_string(), Json::from(self.reads));
+        json.insert("writes".to_string(), Json::from(self.writes));
+        json.insert("bytes_read".to_string(), Json::from(self.bytes_read));
+        json.insert("bytes_written".to_string(), Json::from(self.bytes_written));
+        Json::Object(json)
+    }
+}
+
+/// Where a `Pipeline`'s time goes: one `ModuleProfile` per module, in pipeline order. See
+/// `Pipeline::with_profiling`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineProfile {
+    evaluations: usize,
+    modules: Vec<ModuleProfile>
+}
+
+impl PipelineProfile {
+    /// How many times the pipeline's modules were run; rejected points are not counted.
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    pub fn modules(&self) -> &[ModuleProfile] {
+        &self.modules
+    }
+
+    pub fn get(&self, module: &str) -> Option<&ModuleProfile> {
+        self.modules.iter().find(|profile| profile.name == module)
+    }
+
+    /// The time spent in every module together.
+    pub fn total_time(&self) -> Duration {
+        self.modules.iter().map(|profile| profile.total_time).sum()
+    }
+
+    pub(crate) fn start_evaluation(&mut self) {
+        self.evaluations += 1;
+    }
+
+    /// Records a call of `module` which took `time` and made the accesses in `entries`, the part
+    /// of the block's access log after the module's `MODULE-START`.
+    pub(crate) fn record(&mut self, module: &str, time: Duration, entries: &[LogEntry], block: &DataBlock) {
+        let index = match self.modules.iter().position(|profile| profile.name == module) {
+            Some(index) => index,
+            None => {
+                self.modules.push(ModuleProfile { name: module.to_string(), ..ModuleProfile::default() });
+                self.modules.len() - 1
+            }
+        };
+        self.modules[index].record(time, entries, block);
+    }
+
+    /// The profile as a JSON document, with times in seconds.
+    pub fn to_json(&self) -> String {
+        let mut json = Map::new();
+        json.insert("evaluations".to_string(), Json::from(self.evaluations));
+        json.insert("total_seconds".to_string(), Json::from(self.total_time().as_secs_f64()));
+        json.insert("modules".to_string(), Json::Array(self.modules.iter().map(ModuleProfile::to_json).collect()));
+        Json::Object(json).to_string()
+    }
+}
+
+/// A table with one row per module, and each module's share of the total time.
+impl fmt::Display for PipelineProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.modules.iter().map(|profile| profile.name.len()).max().unwrap_or(0).max("module".len());
+        writeln!(f, "{} evaluations, {:.3} s in modules", self.evaluations, self.total_time().as_secs_f64())?;
+        writeln!(f, "{:<width$} {:>6} {:>10} {:>10} {:>10} {:>6} {:>7} {:>7} {:>12} {:>12}",
+                 "module", "calls", "total (s)", "mean (ms)", "max (ms)", "share", "reads", "writes",
+                 "read (B)", "written (B)", width = width)?;
+        let total = self.total_time().as_secs_f64();
+        for profile in &self.modules {
+            let share = if total > 0.0 { 100.0 * profile.total_time.as_secs_f64() / total } else { 0.0 };
+            writeln!(f, "{:<width$} {:>6} {:>10.3} {:>10.3} {:>10.3} {:>5.1}% {:>7} {:>7} {:>12} {:>12}",
+                     profile.name, profile.calls, profile.total_time.as_secs_f64(),
+                     1e3 * profile.mean_time().as_secs_f64(), 1e3 * profile.max_time.as_secs_f64(), share,
+                     profile.reads, profile.writes, profile.bytes_read, profile.bytes_written, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+impl DataBlock {
+    /// The size in bytes of a numeric entry, found without reading it so that nothing is logged.
+    /// `None` for missing entries, strings, and multi-dimensional arrays.
+    fn value_size(&self, section: &str, name: &str) -> Option<usize> {
+        let element = match self.get_type(section, name)? {
+            datablock_type_t::DBT_INT | datablock_type_t::DBT_INT1D => mem::size_of::<raw::c_int>(),
+            datablock_type_t::DBT_BOOL => mem::size_of::<bool>(),
+            datablock_type_t::DBT_DOUBLE | datablock_type_t::DBT_DOUBLE1D => mem::size_of::<f64>(),
+            datablock_type_t::DBT_COMPLEX | datablock_type_t::DBT_COMPLEX1D => mem::size_of::<Complex<f64>>(),
+            _ => return None
+        };
+        let length = with_keys(section, name, |section, name| {
+            Ok(unsafe { bindings::root::c_datablock_get_array_length(self.ptr, section.as_ptr(), name.as_ptr()) })
+        }).ok()?;
+        // Scalars have no array length.
+        Some(element * if length < 0 { 1 } else { length as usize })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use {DataBlock, LogOperation};
+    use super::PipelineProfile;
+
+    #[test]
+    fn test_record() {
+        let mut block = DataBlock::new();
+        block.put("params", "x", 1.0).unwrap();
+        block.log_access(LogOperation::ModuleStart.as_str(), "theory", "").unwrap();
+        let log_start = block.log_len();
+        block.get::<f64>("params", "x").unwrap();
+        block.put::<[f64], _>("theory", "y", &[1.0, 2.0, 3.0][..]).unwrap();
+        assert!(block.get::<f64>("params", "missing").is_err());
+
+        let mut profile = PipelineProfile::default();
+        profile.start_evaluation();
+        let log = block.access_log_since(log_start).unwrap();
+        assert_eq!(log.len(), 3);
+        profile.record("theory", Duration::from_millis(3), &log, &block);
+        profile.record("theory", Duration::from_millis(1), &log, &block);
+
+        let theory = profile.get("theory").unwrap();
+        assert_eq!((theory.calls, theory.reads, theory.writes), (2, 2, 2));
+        assert_eq!((theory.bytes_read, theory.bytes_written), (16, 48));
+        assert_eq!((theory.min_time, theory.max_time), (Duration::from_millis(1), Duration::from_millis(3)));
+        assert_eq!(theory.mean_time(), Duration::from_millis(2));
+        assert!(profile.to_string().contains("theory"));
+        assert!(profile.to_json().starts_with("{\"evaluations\":1,"), "{}", profile.to_json());
+    }
+}
@@ -0,0 +1,163 @@
+use std::os::raw;
+
+use super::{Complex, CosmosisError, CosmosisGettable, CosmosisResult, CosmosisStorable, DataBlock};
+
+/// Structs which are read from and written to a fixed set of `DataBlock` entries, so that a
+/// module's inputs and outputs are declared in one place. Usually derived:
+///
+/// ```
+/// # extern crate cosmosis;
+/// # use cosmosis::{CosmosisSection, DataBlock};
+/// #[derive(CosmosisSection)]
+/// #[cosmosis(section = "cosmological_parameters")]
+/// struct Cosmology {
+///     omega_m: f64,
+///     #[cosmosis(name = "h0", default = 0.7)]
+///     hubble: f64,
+///     #[cosmosis(optional)]
+///     w: Option<f64>,
+///     #[cosmosis(section = "distances")]
+///     z: Vec<f64>,
+/// }
+/// # fn main() {
+/// # let mut db = DataBlock::new();
+/// # db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+/// # db.put::<[f64], _>("distances", "z", &[0.0, 1.0][..]).unwrap();
+/// let cosmology = Cosmology::read(&db).unwrap();
+/// assert_eq!(cosmology.hubble, 0.7);
+/// assert_eq!(cosmology.w, None);
+/// cosmology.write(&mut db).unwrap();
+/// # }
+/// ```
+///
+/// Fields are read from `(section, name)`, where the section comes from the struct's
+/// `#[cosmosis(section = "...")]` unless the field gives its own, and the name defaults to the
+/// field's name. A missing entry is an error, unless the field has a `default`, which is used
+/// instead, or is `optional`, in which case it must be an `Option` and is read as `None`.
+/// `write` inserts or overwrites every field, skipping `optional` fields which are `None`.
+///
+/// Errors name the field which failed, e.g. `Cosmology::omega_m`.
+pub trait CosmosisSection: Sized {
+    fn read(db: &DataBlock) -> CosmosisResult<Self>;
+    fn write(&self, db: &mut DataBlock) -> CosmosisResult<()>;
+}
+
+/// Types which may be fields of a `CosmosisSection`: they can be read from a `DataBlock`, and are
+/// written back as their `Stored` form, e.g. `Vec<f64>` as `[f64]`.
+pub trait SectionField: CosmosisGettable {
+    type Stored: CosmosisStorable + ?Sized;
+    fn as_stored(&self) -> &Self::Stored;
+}
+
+macro_rules! gen_section_field {
+    ( $rust_name:ty, $stored:ty ) => {
+        impl SectionField for $rust_name {
+            type Stored = $stored;
+            fn as_stored(&self) -> &Self::Stored {
+                self
+            }
+        }
+    }
+}
+
+gen_section_field!(raw::c_int, raw::c_int);
+gen_section_field!(bool, bool);
+gen_section_field!(f64, f64);
+gen_section_field!(Complex<f64>, Complex<f64>);
+gen_section_field!(String, str);
+gen_section_field!(Vec<raw::c_int>, [raw::c_int]);
+gen_section_field!(Vec<f64>, [f64]);
+gen_section_field!(Vec<Complex<f64>>, [Complex<f64>]);
+
+fn field_error(err: CosmosisError, action: &str, field: &str) -> CosmosisError {
+    let reason = match err.reason {
+        Some(ref reason) => format!("Could not {} field {}: {}", action, field, reason),
+        None => format!("Could not {} field {}: {}", action, field, err.kind)
+    };
+    CosmosisError::new(err.kind).with_reason(reason)
+}
+
+#[doc(hidden)]
+pub fn read_field<T>(db: &DataBlock, section: &str, name: &str, field: &str) -> CosmosisResult<T>
+    where T: CosmosisGettable {
+    db.get::<T>(section, name).map_err(|err| field_error(err, "read", field))
+}
+
+#[doc(hidden)]
+pub fn read_field_or_else<T, F>(db: &DataBlock, section: &str, name: &str, field: &str, default: F)
+                                -> CosmosisResult<T>
+    where T: CosmosisGettable,
+          F: FnOnce() -> T {
+    if db.contains(section, name) {
+        read_field(db, section, name, field)
+    } else {
+        Ok(default())
+    }
+}
+
+#[doc(hidden)]
+pub fn read_optional_field<T>(db: &DataBlock, section: &str, name: &str, field: &str) -> CosmosisResult<Option<T>>
+    where T: CosmosisGettable {
+    if db.contains(section, name) {
+        read_field(db, section, name, field).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[doc(hidden)]
+pub fn write_field<T>(db: &mut DataBlock, section: &str, name: &str, field: &str, value: &T) -> CosmosisResult<()>
+    where T: SectionField {
+    db.put_or_overwrite::<T::Stored, _>(section, name, value.as_stored())
+      .map_err(|err| field_error(err, "write", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw;
+    use {CosmosisSection, DataBlock, DATABLOCK_STATUS};
+
+    #[derive(CosmosisSection, Debug, PartialEq)]
+    #[cosmosis(section = "cosmological_parameters")]
+    struct Cosmology {
+        omega_m: f64,
+        #[cosmosis(name = "h0", default = 0.7)]
+        hubble: f64,
+        #[cosmosis(default = 3)]
+        n_massive: raw::c_int,
+        #[cosmosis(optional)]
+        label: Option<String>,
+        #[cosmosis(section = "distances")]
+        z: Vec<f64>,
+    }
+
+    #[test]
+    fn test_derived_section() {
+        let mut db = DataBlock::new();
+        db.put("cosmological_parameters", "omega_m", 0.3).unwrap();
+        db.put::<[f64], _>("distances", "z", &[0.0, 1.0][..]).unwrap();
+
+        let mut cosmology = Cosmology::read(&db).unwrap();
+        assert_eq!(cosmology, Cosmology { omega_m: 0.3, hubble: 0.7, n_massive: 3, label: None,
+                                          z: vec![0.0, 1.0] });
+
+        cosmology.omega_m = 0.25;
+        cosmology.label = Some("planck".to_string());
+        cosmology.write(&mut db).unwrap();
+        assert_eq!(db.get::<f64>("cosmological_parameters", "h0").unwrap(), 0.7);
+        assert_eq!(Cosmology::read(&db).unwrap(), cosmology);
+    }
+
+    #[test]
+    fn test_derived_section_errors() {
+        let mut db = DataBlock::new();
+        db.put::<[f64], _>("distances", "z", &[0.0][..]).unwrap();
+        let err = Cosmology::read(&db).unwrap_err();
+        assert!(err.to_string().contains("Cosmology::omega_m"), "{}", err);
+
+        db.put::<str, _>("cosmological_parameters", "omega_m", "0.3").unwrap();
+        let err = Cosmology::read(&db).unwrap_err();
+        assert_eq!(err.kind, DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE);
+        assert!(err.to_string().contains("Cosmology::omega_m"), "{}", err);
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{CosmosisError, CosmosisResult, DataBlock, DATABLOCK_STATUS};
+use diff::{BlockDiff, Tolerance};
+
+/// The stage name of the block before any module ran.
+pub const PARAMETERS_STAGE: &str = "parameters";
+
+/// How `DebugSnapshots` saves each block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// The CosmoSIS test sampler's layout; see `DataBlock::save_to_directory`.
+    #[default]
+    Directory,
+    /// One document per block; see `DataBlock::to_json`.
+    Json
+}
+
+/// The block as it stood after one stage of an evaluation.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// The module which just ran, or `PARAMETERS_STAGE` for the block before any module ran.
+    pub stage: String,
+    pub block: DataBlock,
+    /// What the stage added or changed, relative to the snapshot before it.
+    pub diff: BlockDiff,
+    /// Whether the module failed, so that `block` holds only its partial writes.
+    pub failed: bool
+}
+
+/// A debug mode for a `Pipeline`, which saves the full block after every module, along with a
+/// diff showing what the module added or changed. See `Pipeline::with_debug_snapshots`.
+///
+/// Evaluation `n` is saved under `<dir>/evaluation_<n>`, as `00_parameters` for the block
+/// before any module ran and `01_<module>`, `02_<module>` and so on after each module, each with
+/// a `.diff` file alongside. If a module fails, its partial writes are saved as
+/// `<NN>_<module>.failed`, so the last good block is the snapshot before it.
+#[derive(Clone, Debug)]
+pub struct DebugSnapshots {
+    dir: PathBuf,
+    format: SnapshotFormat,
+    tolerance: Tolerance,
+    evaluations: usize,
+    snapshots: Vec<Snapshot>
+}
+
+impl DebugSnapshots {
+    /// Saves snapshots under `dir` in `SnapshotFormat::Directory`, and reports any change in a
+    /// value, however small.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DebugSnapshots { dir: dir.into(), format: SnapshotFormat::default(), tolerance: Tolerance::exact(),
+                         evaluations: 0, snapshots: Vec::new() }
+    }
+
+    pub fn format(mut self, format: SnapshotFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets how much a value may change before the diffs report it.
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The snapshots of the latest evaluation, in the order they were taken.
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    /// The latest block which no module failed to finish writing.
+    pub fn last_good(&self) -> Option<&DataBlock> {
+        self.snapshots.iter().rev().find(|snapshot| !snapshot.failed).map(|snapshot| &snapshot.block)
+    }
+
+    fn evaluation_dir(&self) -> PathBuf {
+        self.dir.join(format!("evaluation_{}", self.evaluations))
+    }
+
+    /// Starts a new evaluation, taking the first snapshot of `block`.
+    pub(crate) fn start(&mut self, block: &DataBlock) -> CosmosisResult<()> {
+        self.evaluations += 1;
+        self.snapshots.clear();
+        let dir = self.evaluation_dir();
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|err| io_error(&dir, err))?;
+        }
+        fs::create_dir_all(&dir).map_err(|err| io_error(&dir, err))?;
+        self.record(PARAMETERS_STAGE, block, false)
+    }
+
+    /// Snapshots `block` after `stage` ran, and saves it and its diff from the previous snapshot.
+    pub(crate) fn record(&mut self, stage: &str, block: &DataBlock, failed: bool) -> CosmosisResult<()> {
+        // Snapshots are taken of a copy, so that reading every value does not add to the access log.
+        let block = block.clone();
+        let diff = match self.last_good() {
+            Some(previous) => previous.diff(&block, self.tolerance),
+            None => DataBlock::new().diff(&block, self.tolerance)
+        };
+
+        let mut path = self.evaluation_dir().join(format!("{:02}_{}", self.snapshots.len(), stage));
+        if failed {
+            path = with_suffix(&path, "failed");
+        }
+        match self.format {
+            SnapshotFormat::Directory => block.save_to_directory(&path)?,
+            SnapshotFormat::Json => {
+                let json_path = with_suffix(&path, "json");
+                fs::write(&json_path, block.to_json()?).map_err(|err| io_error(&json_path, err))?;
+            }
+        }
+        let diff_path = with_suffix(&path, "diff");
+        fs::write(&diff_path, format!("{}\n", diff)).map_err(|err| io_error(&diff_path, err))?;
+
+        self.snapshots.push(Snapshot { stage: stage.to_string(), block, diff, failed });
+        Ok(())
+    }
+}
+
+/// Appends `.suffix` to `path`, keeping any extension it has.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn io_error(path: &Path, err: ::std::io::Error) -> CosmosisError {
+    CosmosisError::new(DATABLOCK_STATUS::DBS_LOGIC_ERROR)
+        .with_reason(format!("Could not save debug snapshot {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DebugSnapshots;
+    use std::{env, fs};
+    use DataBlock;
+
+    #[test]
+    fn test_directory_snapshots() {
+        let dir = env::temp_dir().join(format!("cosmosis-snapshots-test-{}", ::std::process::id()));
+        let mut snapshots = DebugSnapshots::new(&dir);
+        let mut block = DataBlock::new();
+        block.put("params", "x", 1.0).unwrap();
+        for _ in 0..2 {
+            snapshots.start(&block).unwrap();
+        }
+        block.overwrite("params", "x", 2.0).unwrap();
+        block.put("theory", "y", 3.0).unwrap();
+        snapshots.record("theory", &block, false).unwrap();
+
+        assert_eq!(snapshots.snapshots().len(), 2);
+        let diff = snapshots.snapshots()[1].diff.to_string();
+        assert!(diff.starts_with("~ params/x: ") && diff.ends_with("\n+ [theory] (section added)"), "{}", diff);
+        let saved = DataBlock::load_from_directory(dir.join("evaluation_2/01_theory")).unwrap();
+        assert_eq!(saved.get::<f64>("params", "x").unwrap(), 2.0);
+        assert!(dir.join("evaluation_1/00_parameters.diff").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
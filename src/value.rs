@@ -0,0 +1,229 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw;
+
+use super::{Complex, CosmosisDataType, CosmosisError, CosmosisGettable, CosmosisResult, CosmosisStorable,
+            DataBlock, DATABLOCK_STATUS, datablock_type_t};
+use ndarray::{ndarray_type, NdArray};
+
+/// The types which have a `Value` representation.
+pub(crate) const SUPPORTED_TYPES: [datablock_type_t; 14] = [
+    datablock_type_t::DBT_INT, datablock_type_t::DBT_BOOL, datablock_type_t::DBT_DOUBLE,
+    datablock_type_t::DBT_COMPLEX, datablock_type_t::DBT_STRING, datablock_type_t::DBT_INT1D,
+    datablock_type_t::DBT_DOUBLE1D, datablock_type_t::DBT_COMPLEX1D, datablock_type_t::DBT_INT2D,
+    datablock_type_t::DBT_DOUBLE2D, datablock_type_t::DBT_COMPLEX2D, datablock_type_t::DBT_INTND,
+    datablock_type_t::DBT_DOUBLEND, datablock_type_t::DBT_COMPLEXND,
+];
+
+/// Arrays longer than this are abbreviated by `Value`'s `Display` impl.
+const DISPLAY_MAX_ELEMENTS: usize = 8;
+
+/// A dynamically-typed `DataBlock` entry. There is one variant for each type
+/// which implements `CosmosisDataType`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(raw::c_int),
+    Bool(bool),
+    Double(f64),
+    Complex(Complex<f64>),
+    String(String),
+    IntArray(Vec<raw::c_int>),
+    DoubleArray(Vec<f64>),
+    ComplexArray(Vec<Complex<f64>>),
+    /// Arrays of two or more dimensions, which CosmoSIS tags as either 2D or ND.
+    IntNdArray(NdArray<raw::c_int>),
+    DoubleNdArray(NdArray<f64>),
+    ComplexNdArray(NdArray<Complex<f64>>),
+}
+
+impl Value {
+    /// The CosmoSIS type tag this value is stored under.
+    pub fn datablock_type(&self) -> datablock_type_t {
+        match *self {
+            Value::Int(_) => raw::c_int::cosmosis_type(),
+            Value::Bool(_) => bool::cosmosis_type(),
+            Value::Double(_) => f64::cosmosis_type(),
+            Value::Complex(_) => Complex::<f64>::cosmosis_type(),
+            Value::String(_) => datablock_type_t::DBT_STRING,
+            Value::IntArray(_) => Vec::<raw::c_int>::cosmosis_type(),
+            Value::DoubleArray(_) => Vec::<f64>::cosmosis_type(),
+            Value::ComplexArray(_) => Vec::<Complex<f64>>::cosmosis_type(),
+            Value::IntNdArray(ref a) =>
+                ndarray_type(a.ndim(), datablock_type_t::DBT_INT2D, datablock_type_t::DBT_INTND),
+            Value::DoubleNdArray(ref a) =>
+                ndarray_type(a.ndim(), datablock_type_t::DBT_DOUBLE2D, datablock_type_t::DBT_DOUBLEND),
+            Value::ComplexNdArray(ref a) =>
+                ndarray_type(a.ndim(), datablock_type_t::DBT_COMPLEX2D, datablock_type_t::DBT_COMPLEXND),
+        }
+    }
+
+    /// The number of elements, if this is an array, counting every element of a
+    /// multi-dimensional one.
+    pub fn len(&self) -> Option<usize> {
+        match *self {
+            Value::IntArray(ref v) => Some(v.len()),
+            Value::DoubleArray(ref v) => Some(v.len()),
+            Value::ComplexArray(ref v) => Some(v.len()),
+            Value::IntNdArray(ref a) => Some(a.data().len()),
+            Value::DoubleNdArray(ref a) => Some(a.data().len()),
+            Value::ComplexNdArray(ref a) => Some(a.data().len()),
+            _ => None
+        }
+    }
+
+    /// The extent of each dimension, if this is an array.
+    pub fn shape(&self) -> Option<Vec<usize>> {
+        match *self {
+            Value::IntNdArray(ref a) => Some(a.shape().to_vec()),
+            Value::DoubleNdArray(ref a) => Some(a.shape().to_vec()),
+            Value::ComplexNdArray(ref a) => Some(a.shape().to_vec()),
+            _ => self.len().map(|len| vec![len])
+        }
+    }
+
+    /// Whether this is a zero-length array.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    pub(crate) fn get_datablock(db: &DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<Self> {
+        let ty = db.get_type(section, name).ok_or_else(|| {
+            CosmosisError::new(DATABLOCK_STATUS::DBS_NAME_NOT_FOUND)
+                .with_reason(format!("No value at (section, name): ({}, {})",
+                                     section.to_string_lossy(), name.to_string_lossy()))
+        })?;
+        Ok(match ty {
+            datablock_type_t::DBT_INT => Value::Int(raw::c_int::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_BOOL => Value::Bool(bool::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_DOUBLE => Value::Double(f64::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_COMPLEX => Value::Complex(Complex::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_STRING => Value::String(String::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_INT1D => Value::IntArray(Vec::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_DOUBLE1D => Value::DoubleArray(Vec::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_COMPLEX1D => Value::ComplexArray(Vec::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_INT2D | datablock_type_t::DBT_INTND =>
+                Value::IntNdArray(NdArray::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_DOUBLE2D | datablock_type_t::DBT_DOUBLEND =>
+                Value::DoubleNdArray(NdArray::get_datablock(db, section, name)?),
+            datablock_type_t::DBT_COMPLEX2D | datablock_type_t::DBT_COMPLEXND =>
+                Value::ComplexNdArray(NdArray::get_datablock(db, section, name)?),
+            other => {
+                return Err(CosmosisError::new(DATABLOCK_STATUS::DBS_WRONG_VALUE_TYPE)
+                           .with_reason(format!("Unsupported type {:?} at (section, name): ({}, {})", other,
+                                                section.to_string_lossy(), name.to_string_lossy())));
+            }
+        })
+    }
+
+    pub(crate) fn put_datablock(&self, db: &mut DataBlock, section: &CStr, name: &CStr) -> CosmosisResult<()> {
+        match *self {
+            Value::Int(n) => raw::c_int::put_datablock(db, section, name, &n),
+            Value::Bool(b) => bool::put_datablock(db, section, name, &b),
+            Value::Double(x) => f64::put_datablock(db, section, name, &x),
+            Value::Complex(z) => Complex::put_datablock(db, section, name, &z),
+            Value::String(ref s) => str::put_datablock(db, section, name, s),
+            Value::IntArray(ref v) => <[raw::c_int]>::put_datablock(db, section, name, v),
+            Value::DoubleArray(ref v) => <[f64]>::put_datablock(db, section, name, v),
+            Value::ComplexArray(ref v) => <[Complex<f64>]>::put_datablock(db, section, name, v),
+            Value::IntNdArray(ref a) => NdArray::put_datablock(db, section, name, a),
+            Value::DoubleNdArray(ref a) => NdArray::put_datablock(db, section, name, a),
+            Value::ComplexNdArray(ref a) => NdArray::put_datablock(db, section, name, a),
+        }
+    }
+}
+
+/// The name of a type tag as it appears in files written by this crate, e.g. `DBT_DOUBLE1D`.
+pub(crate) fn type_tag(ty: datablock_type_t) -> String {
+    format!("{:?}", ty)
+}
+
+/// The inverse of `type_tag`, for the types which have a `Value` representation.
+pub(crate) fn parse_type_tag(tag: &str) -> Option<datablock_type_t> {
+    SUPPORTED_TYPES.iter().copied().find(|&ty| type_tag(ty) == tag)
+}
+
+struct DisplayComplex<'a>(&'a Complex<f64>);
+
+impl<'a> fmt::Display for DisplayComplex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{:+}i", self.0.re, self.0.im)
+    }
+}
+
+fn fmt_array<T, F>(f: &mut fmt::Formatter, values: &[T], fmt_one: F) -> fmt::Result
+    where F: Fn(&mut fmt::Formatter, &T) -> fmt::Result {
+    let abbreviate = values.len() > DISPLAY_MAX_ELEMENTS;
+    let (head, tail) = if abbreviate {
+        (&values[..DISPLAY_MAX_ELEMENTS / 2], &values[values.len() - DISPLAY_MAX_ELEMENTS / 2..])
+    } else {
+        (values, &values[values.len()..])
+    };
+
+    write!(f, "[")?;
+    for (i, v) in head.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        fmt_one(f, v)?;
+    }
+    if abbreviate {
+        write!(f, ", ...")?;
+        for v in tail {
+            write!(f, ", ")?;
+            fmt_one(f, v)?;
+        }
+    }
+    write!(f, "]")?;
+    if abbreviate {
+        write!(f, " ({} elements)", values.len())?;
+    }
+    Ok(())
+}
+
+/// Multi-dimensional arrays are printed flattened, followed by their shape.
+fn fmt_ndarray<T, F>(f: &mut fmt::Formatter, array: &NdArray<T>, fmt_one: F) -> fmt::Result
+    where F: Fn(&mut fmt::Formatter, &T) -> fmt::Result {
+    fmt_array(f, array.data(), fmt_one)?;
+    let shape: Vec<String> = array.shape().iter().map(|n| n.to_string()).collect();
+    write!(f, " (shape {})", shape.join("x"))
+}
+
+/// Scalars are printed plainly, strings quoted, and arrays longer than a few
+/// elements are abbreviated to their first and last elements.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Double(x) => write!(f, "{}", x),
+            Value::Complex(ref z) => write!(f, "{}", DisplayComplex(z)),
+            Value::String(ref s) => write!(f, "{:?}", s),
+            Value::IntArray(ref v) => fmt_array(f, v, |f, n| write!(f, "{}", n)),
+            Value::DoubleArray(ref v) => fmt_array(f, v, |f, x| write!(f, "{}", x)),
+            Value::ComplexArray(ref v) => fmt_array(f, v, |f, z| write!(f, "{}", DisplayComplex(z))),
+            Value::IntNdArray(ref a) => fmt_ndarray(f, a, |f, n| write!(f, "{}", n)),
+            Value::DoubleNdArray(ref a) => fmt_ndarray(f, a, |f, x| write!(f, "{}", x)),
+            Value::ComplexNdArray(ref a) => fmt_ndarray(f, a, |f, z| write!(f, "{}", DisplayComplex(z))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Complex, NdArray, Value};
+
+    #[test]
+    fn test_display_value() {
+        assert_eq!(Value::Int(3).to_string(), "3");
+        assert_eq!(Value::String("hi".to_string()).to_string(), "\"hi\"");
+        assert_eq!(Value::Complex(Complex { re: 1.0, im: -2.0 }).to_string(), "1-2i");
+        assert_eq!(Value::DoubleArray(vec![0.5, 1.0]).to_string(), "[0.5, 1]");
+
+        let long = Value::IntArray((0..100).collect());
+        assert_eq!(long.to_string(), "[0, 1, 2, 3, ..., 96, 97, 98, 99] (100 elements)");
+
+        let matrix = Value::IntNdArray(NdArray::new(vec![2, 2], vec![1, 2, 3, 4]).unwrap());
+        assert_eq!(matrix.to_string(), "[1, 2, 3, 4] (shape 2x2)");
+        assert_eq!(matrix.shape(), Some(vec![2, 2]));
+    }
+}